@@ -0,0 +1,16 @@
+use adventofcode_solutions_2019::day10::{generator, part1, part1_hashed, best_station_parallel, default_threads, synthetic_field};
+use criterion::{criterion_group, criterion_main, Criterion};
+
+fn best_station(c: &mut Criterion) {
+    let field = generator(&synthetic_field(80, 80, 30, 2019));
+    let mut group = c.benchmark_group("day10 best station");
+    group.sample_size(10);
+    group.bench_function("sorted", |b| b.iter(|| part1(&field)));
+    group.bench_function("hashed", |b| b.iter(|| part1_hashed(&field)));
+    let threads = default_threads();
+    group.bench_function(format!("parallel ({} threads)", threads), |b| b.iter(|| best_station_parallel(&field, threads)));
+    group.finish();
+}
+
+criterion_group!(benches, best_station);
+criterion_main!(benches);
@@ -0,0 +1,17 @@
+use adventofcode_solutions_2019::day6::{part1_iterative, part1_orbit_map, part1_rec, synthetic_orbits};
+use criterion::{criterion_group, criterion_main, Criterion};
+
+fn total_orbits(c: &mut Criterion) {
+    for (shape, bodies, branching) in [("deep", 10_000, 1), ("wide", 100_000, 16)] {
+        let orbits = synthetic_orbits(bodies, branching);
+        let mut group = c.benchmark_group(format!("day6 total orbits {}", shape));
+        group.sample_size(20);
+        group.bench_function("recursive", |b| b.iter(|| part1_rec(&orbits)));
+        group.bench_function("iterative", |b| b.iter(|| part1_iterative(&orbits)));
+        group.bench_function("orbit_map", |b| b.iter(|| part1_orbit_map(&orbits)));
+        group.finish();
+    }
+}
+
+criterion_group!(benches, total_orbits);
+criterion_main!(benches);
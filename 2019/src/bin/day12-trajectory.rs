@@ -0,0 +1,67 @@
+extern crate adventofcode_solutions_2019;
+
+use adventofcode_solutions_2019::day12::{parse_bodies, write_trajectory, Simulation, TrajectoryFormat};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::process::exit;
+
+const USAGE: &str = "usage: day12-trajectory [--steps N] [--format csv|jsonl] [--output FILE] [INPUT]
+
+Simulates the moons from INPUT (default: input/2019/day12.txt) and writes the initial state
+followed by N steps (default: 1000) to FILE (default: stdout). The format defaults to jsonl if
+FILE ends in .jsonl and csv otherwise.";
+
+struct Options {
+    steps: usize,
+    format: Option<TrajectoryFormat>,
+    output: Option<String>,
+    input: String,
+}
+
+fn parse_args(mut args: impl Iterator<Item=String>) -> Result<Options, String> {
+    let mut options = Options { steps: 1000, format: None, output: None, input: "input/2019/day12.txt".to_string() };
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or_else(|| format!("missing value for {}", name));
+        match arg.as_str() {
+            "--steps" | "-n" => options.steps = value(&arg)?.parse().map_err(|e| format!("invalid number of steps: {}", e))?,
+            "--format" | "-f" => options.format = Some(value(&arg)?.parse()?),
+            "--output" | "-o" => options.output = Some(value(&arg)?),
+            "--help" | "-h" => return Err(USAGE.to_string()),
+            flag if flag.starts_with('-') => return Err(format!("unknown option {}\n\n{}", flag, USAGE)),
+            _ => options.input = arg,
+        }
+    }
+    Ok(options)
+}
+
+fn main() {
+    let options = parse_args(std::env::args().skip(1)).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        exit(2)
+    });
+    let input = std::fs::read_to_string(&options.input).unwrap_or_else(|e| {
+        eprintln!("cannot read {}: {}", options.input, e);
+        exit(1)
+    });
+    let format = options.format.unwrap_or(match &options.output {
+        Some(path) if path.ends_with(".jsonl") => TrajectoryFormat::JsonLines,
+        _ => TrajectoryFormat::Csv
+    });
+    let states = Simulation::new(&parse_bodies(&input)).take(options.steps + 1);
+    let result = match &options.output {
+        Some(path) => File::create(path).and_then(|f| {
+            let mut out = BufWriter::new(f);
+            write_trajectory(&mut out, format, states)?;
+            out.flush()
+        }),
+        None => {
+            let stdout = io::stdout();
+            let mut out = BufWriter::new(stdout.lock());
+            write_trajectory(&mut out, format, states).and_then(|_| out.flush())
+        }
+    };
+    if let Err(e) = result {
+        eprintln!("cannot write trajectory: {}", e);
+        exit(1)
+    }
+}
@@ -0,0 +1,80 @@
+extern crate adventofcode_solutions_2019;
+
+use adventofcode_solutions_2019::{day14, day6};
+use std::process::exit;
+
+const USAGE: &str = "usage: dot-export <day6|day14> [--from BODY] [--to BODY] [--output FILE] [INPUT]
+
+Writes the day 6 orbit map or the day 14 reactions from INPUT (default: input/2019/<day>.txt) as a
+Graphviz DOT file to FILE (default: <day>.dot). For day 6, the orbital transfer path from the object
+--from (default: YOU) orbits to the object --to (default: SAN) orbits is highlighted, if both exist.";
+
+struct Options {
+    day: String,
+    from: String,
+    to: String,
+    output: Option<String>,
+    input: Option<String>,
+}
+
+fn parse_args(mut args: impl Iterator<Item=String>) -> Result<Options, String> {
+    let mut options = Options { day: String::new(), from: "YOU".to_string(), to: "SAN".to_string(), output: None, input: None };
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or_else(|| format!("missing value for {}", name));
+        match arg.as_str() {
+            "--from" => options.from = value(&arg)?,
+            "--to" => options.to = value(&arg)?,
+            "--output" | "-o" => options.output = Some(value(&arg)?),
+            "--help" | "-h" => return Err(USAGE.to_string()),
+            flag if flag.starts_with('-') => return Err(format!("unknown option {}\n\n{}", flag, USAGE)),
+            _ if options.day.is_empty() => options.day = arg,
+            _ => options.input = Some(arg),
+        }
+    }
+    match options.day.as_str() {
+        "day6" | "day14" => Ok(options),
+        "" => Err(USAGE.to_string()),
+        other => Err(format!("unsupported day {}, expected day6 or day14\n\n{}", other, USAGE)),
+    }
+}
+
+fn main() {
+    let options = parse_args(std::env::args().skip(1)).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        exit(2)
+    });
+    let input_path = options.input.clone().unwrap_or_else(|| format!("input/2019/{}.txt", options.day));
+    let input = std::fs::read_to_string(&input_path).unwrap_or_else(|e| {
+        eprintln!("cannot read {}: {}", input_path, e);
+        exit(1)
+    });
+
+    // rendered in memory, so that invalid input doesn't clobber an earlier export
+    let mut out = Vec::new();
+    let rendered = if options.day == "day6" {
+        let map = day6::OrbitMap::new(&day6::generator(&input)).unwrap_or_else(|e| {
+            eprintln!("invalid orbit map: {}", e);
+            exit(1)
+        });
+        let highlight = map.transfer_path(&options.from, &options.to).unwrap_or_else(|e| {
+            eprintln!("not highlighting a transfer path: {}", e);
+            Vec::new()
+        });
+        map.write_dot(&mut out, &highlight)
+    } else {
+        let recipes = day14::parse_recipes(&input).unwrap_or_else(|e| {
+            eprintln!("invalid reactions: {}", e);
+            exit(1)
+        });
+        // invalid reaction graphs (e.g. with cycles) are exactly the ones worth looking at
+        if let Err(e) = day14::Reactions::new(recipes.clone()) {
+            eprintln!("warning: {}", e);
+        }
+        day14::write_dot(&mut out, &recipes)
+    };
+    let output_path = options.output.clone().unwrap_or_else(|| format!("{}.dot", options.day));
+    if let Err(e) = rendered.and_then(|_| std::fs::write(&output_path, &out)) {
+        eprintln!("cannot write {}: {}", output_path, e);
+        exit(1)
+    }
+}
@@ -0,0 +1,78 @@
+extern crate adventofcode_solutions_2019;
+
+use adventofcode_solutions_2019::day11::{generator, paint_recording, Color};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::process::exit;
+
+const USAGE: &str = "usage: hull-export [--start black|white] [--scale N] [--output FILE] [--animation FILE] [INPUT]
+
+Runs the painting robot program from INPUT (default: input/2019/day11.txt), starting on a panel of
+the given color (default: white, as in part 2). Writes the painted hull as a PBM image to FILE
+(default: day11.pbm) and every step of the robot as a multi-image PBM stream to the animation FILE
+(default: day11-animation.pbm). Each panel is drawn as an NxN square (default: 4).";
+
+struct Options {
+    start: Color,
+    scale: usize,
+    output: String,
+    animation: String,
+    input: String,
+}
+
+fn parse_args(mut args: impl Iterator<Item=String>) -> Result<Options, String> {
+    let mut options = Options {
+        start: Color::White,
+        scale: 4,
+        output: "day11.pbm".to_string(),
+        animation: "day11-animation.pbm".to_string(),
+        input: "input/2019/day11.txt".to_string(),
+    };
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or_else(|| format!("missing value for {}", name));
+        match arg.as_str() {
+            "--start" => options.start = match value(&arg)?.as_str() {
+                "black" => Color::Black,
+                "white" => Color::White,
+                other => return Err(format!("invalid start color {}, expected black or white", other)),
+            },
+            "--scale" | "-s" => options.scale = match value(&arg)?.parse() {
+                Ok(0) => return Err("scale must be at least 1".to_string()),
+                Ok(scale) => scale,
+                Err(e) => return Err(format!("invalid scale: {}", e)),
+            },
+            "--output" | "-o" => options.output = value(&arg)?,
+            "--animation" | "-a" => options.animation = value(&arg)?,
+            "--help" | "-h" => return Err(USAGE.to_string()),
+            flag if flag.starts_with('-') => return Err(format!("unknown option {}\n\n{}", flag, USAGE)),
+            _ => options.input = arg,
+        }
+    }
+    Ok(options)
+}
+
+fn write_file(path: &str, write: impl FnOnce(&mut BufWriter<File>) -> std::io::Result<()>) {
+    let result = File::create(path).and_then(|f| {
+        let mut out = BufWriter::new(f);
+        write(&mut out)?;
+        out.flush()
+    });
+    if let Err(e) = result {
+        eprintln!("cannot write {}: {}", path, e);
+        exit(1)
+    }
+}
+
+fn main() {
+    let options = parse_args(std::env::args().skip(1)).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        exit(2)
+    });
+    let input = std::fs::read_to_string(&options.input).unwrap_or_else(|e| {
+        eprintln!("cannot read {}: {}", options.input, e);
+        exit(1)
+    });
+    let hull = paint_recording(&generator(&input), options.start);
+    write_file(&options.output, |out| hull.write_pbm(out, options.scale));
+    write_file(&options.animation, |out| hull.write_animation_pbm(out, options.scale));
+}
@@ -2,6 +2,11 @@ use itertools::Itertools;
 use std::collections::BTreeMap;
 use std::collections::btree_map::Entry;
 use std::cmp::Ordering;
+use crate::number_theory::gcd_u64;
+use fxhash::FxHashSet;
+use std::sync::Arc;
+use std::sync::mpsc::channel;
+use threadpool_crossbeam_channel::ThreadPool;
 
 type Scalar = isize;
 type Point2 = (Scalar, Scalar);
@@ -19,68 +24,38 @@ pub fn generator(input: &str) -> Vec<Point2> {
         ).collect()
 }
 
-#[derive(PartialEq,Eq,Debug,Clone,Copy)]
-enum Direction {
-    North,
-    NorthEast(isize,isize),
-    East,
-    SouthEast(isize,isize),
-    South,
-    SouthWest(isize,isize),
-    West,
-    NorthWest(isize,isize)
+/// Exact direction of a vector from the station, measured clockwise starting at North (negative y).
+/// The vector is reduced by the GCD of its components, so two vectors compare equal iff they point
+/// the same way. Ordering uses cross products only, no floating point.
+#[derive(PartialEq,Eq,Hash,Debug,Clone,Copy)]
+struct Direction {
+    x: Scalar,
+    y: Scalar,
 }
+
 impl Direction {
-    fn normalized(self) -> Self {
-        fn norm(f: fn (isize,isize) -> Direction, x: isize, y: isize) -> Direction {
-            let (nx, ny) = normalized_num(x,y);
-            f(nx,ny)
-        }
-        match self {
-            Direction::NorthEast(x,y) => norm(Direction::NorthEast, x, y),
-            Direction::SouthEast(x,y) => norm(Direction::SouthEast, x, y),
-            Direction::SouthWest(x,y) => norm(Direction::SouthWest, x, y),
-            Direction::NorthWest(x,y) => norm(Direction::NorthWest, x, y),
-            x => panic!("normalized not supported for {:?}", x)
+    /// `None` for the zero vector, which doesn't have a direction.
+    fn of((x,y): Vector2) -> Option<Direction> {
+        if x == 0 && y == 0 {
+            return None;
         }
+        let g = gcd_u64(x.unsigned_abs() as u64, y.unsigned_abs() as u64) as Scalar;
+        Some(Direction { x: x/g, y: y/g })
+    }
+
+    /// 0 for directions from North (inclusive) to South (exclusive) on the East side, 1 for the rest.
+    fn half(&self) -> u8 {
+        if self.x > 0 || (self.x == 0 && self.y < 0) { 0 } else { 1 }
     }
 }
 
 impl Ord for Direction {
     fn cmp(&self, other: &Self) -> Ordering {
-        use Direction::*;
-        use Ordering::*;
-        match (self,other) {
-            (North,North) => Equal,
-            (North,_) => Less,
-            (NorthEast(_,_), North) => Greater,
-            (NorthEast(x1,y1), NorthEast(x2,y2)) => (x1.abs() as f64/y1.abs() as f64).partial_cmp(&(x2.abs() as f64/y2.abs() as f64)).expect("a number"),
-            (NorthEast(_,_), _) => Less,
-            (East,North) => Greater,
-            (East,NorthWest(_,_)) => Greater,
-            (East,East) => Equal,
-            (East,_) => Less,
-            (SouthEast(_,_),North) => Greater,
-            (SouthEast(_,_),NorthEast(_,_)) => Greater,
-            (SouthEast(_,_),East) => Greater,
-            (SouthEast(x1,y1), SouthEast(x2,y2)) => (y1.abs() as f64/x1.abs() as f64).partial_cmp(&(y2.abs() as f64/x2.abs() as f64)).expect("a number"),
-            (SouthEast(_,_), _) => Less,
-            (South,North) => Greater,
-            (South,NorthEast(_,_)) => Greater,
-            (South,East) => Greater,
-            (South,SouthEast(_,_)) => Greater,
-            (South, South) => Equal,
-            (South, _) => Less,
-            (SouthWest(x1, y1), SouthWest(x2, y2)) => (x1.abs() as f64/y1.abs() as f64).partial_cmp(&(x2.abs() as f64/y2.abs() as f64)).expect("a number"),
-            (SouthWest(_,_),West) => Less,
-            (SouthWest(_,_),NorthWest(_,_)) => Less,
-            (SouthWest(_,_),_) => Greater,
-            (West,West) => Equal,
-            (West,NorthWest(_,_)) => Less,
-            (West,_) => Greater,
-            (NorthWest(x1,y1),NorthWest(x2,y2)) => (y1.abs() as f64/x1.abs() as f64).partial_cmp(&(y2.abs() as f64/x2.abs() as f64)).expect("a number"),
-            (NorthWest(_,_),_) => Greater
-        }
+        // Within one half, `self` comes first iff `other` is clockwise from it, i.e. the cross product
+        // is positive (y points down). The reduced components are at most the input coordinates,
+        // so the products can't overflow for any realistic map.
+        self.half().cmp(&other.half())
+            .then_with(|| (other.x * self.y).cmp(&(self.x * other.y)))
     }
 }
 impl PartialOrd for Direction {
@@ -89,62 +64,14 @@ impl PartialOrd for Direction {
     }
 }
 
-fn normalized_num(on: isize, od: isize) -> (isize, isize) {
-    // GCD https://en.wikipedia.org/wiki/Greatest_common_divisor (binary)
-    let mut a = on.abs();
-    let mut b = od.abs();
-    let mut d = 0;
-
-    #[inline(always)]
-    fn is_even(x: Scalar) -> bool { (x&1) == 0 }
-
-    while is_even(a) && is_even(b) {
-        a = a>>1;
-        b = b>>1;
-        d += 1;
-    }
-
-    while a != b {
-        if is_even(a) { a = a>>1; }
-        else if is_even(b) { b = b>>1; }
-        else if a > b { a = (a - b)>>1; }
-        else { b = (b - a)>>1; }
-    }
-    let gcd = a<<d;
-    (on/gcd, od/gcd)
-}
-impl From<Vector2> for Direction {
-    fn from((x,y): (isize, isize)) -> Self {
-        if x == 0 && y == 0 {
-            panic!("NOPE ORIGIN");
-        } else if x == 0 && y < 0 {
-            Direction::North
-        } else if x > 0 && y < 0 {
-            Direction::NorthEast(x,y).normalized()
-        } else if x > 0 && y == 0 {
-            Direction::East
-        } else if x > 0 && y > 0 {
-            Direction::SouthEast(x,y).normalized()
-        } else if x == 0 && y > 0 {
-            Direction::South
-        } else if x < 0 && y > 0 {
-            Direction::SouthWest(x,y).normalized()
-        } else if x < 0 && y == 0 {
-            Direction::West
-        } else if x < 0 && y < 0 {
-            Direction::NorthWest(x,y).normalized()
-        } else {
-            panic!("9th direction {},{}", x, y);
-        }
-    }
+fn direction(center: &Point2, remote: &Point2) -> Direction {
+    Direction::of((remote.0 - center.0, remote.1 - center.1)).expect("remote asteroid at the center")
 }
 
 fn visible_from(center: &Point2, input: &[Point2]) -> usize {
     input.iter()
         .filter(|p| *p != center)
-        .map(|remote| {
-            Direction::from((remote.0 - center.0, remote.1 - center.1))
-        })
+        .map(|remote| direction(center, remote))
         .sorted()
         .dedup()
         .count()
@@ -153,9 +80,8 @@ fn visible_from(center: &Point2, input: &[Point2]) -> usize {
 fn angular_frequency(center: &Point2, input: &[Point2], freq: &mut BTreeMap<Direction, Vec<Point2>>) {
     input.iter()
         .filter(|p| *p != center)
-        .map(|remote| {
-            (Direction::from((remote.0 - center.0, remote.1 - center.1)), remote)
-        }).for_each(move |(d,p)| {
+        .map(|remote| (direction(center, remote), remote))
+        .for_each(move |(d,p)| {
         match freq.entry(d) {
             Entry::Vacant(v) => {
                 v.insert(vec![*p]);
@@ -178,42 +104,163 @@ pub fn part1(input: &Vec<Point2>) -> usize {
     best_part_1(input).1
 }
 
+/// Same as `visible_from`, but dedups the directions with a hash set instead of sorting them.
+fn visible_from_hashed(center: &Point2, input: &[Point2]) -> usize {
+    input.iter()
+        .filter(|p| *p != center)
+        .map(|remote| direction(center, remote))
+        .collect::<FxHashSet<_>>()
+        .len()
+}
+
+fn best_hashed(input: &[Point2]) -> (&Point2, usize) {
+    input.iter().map(|center| {
+        (center, visible_from_hashed(center, input))
+    }).max_by(|l,r| l.1.cmp(&r.1)).expect("Expected asteroids")
+}
+
+/// Evaluates the candidate stations on `threads` threads. Picks the same station as `best_part_1` on ties.
+pub fn best_station_parallel(input: &[Point2], threads: usize) -> (Point2, usize) {
+    let field = Arc::new(input.to_vec());
+    let pool = ThreadPool::new(threads.max(1));
+    let (tx, rx) = channel();
+    let chunk_size = (field.len() / (threads.max(1) * 4)).max(1);
+    for start in (0..field.len()).step_by(chunk_size) {
+        let field = field.clone();
+        let tx = tx.clone();
+        pool.execute(move || {
+            let end = (start + chunk_size).min(field.len());
+            for i in start..end {
+                tx.send((visible_from_hashed(&field[i], &field), i)).expect("receiver alive");
+            }
+        });
+    }
+    drop(tx);
+    let (visible, i) = rx.iter().max().expect("Expected asteroids");
+    (field[i], visible)
+}
+
+#[aoc(day10, part1, hashed)]
+pub fn part1_hashed(input: &Vec<Point2>) -> usize {
+    best_hashed(input).1
+}
+
+#[aoc(day10, part1, parallel)]
+pub fn part1_parallel(input: &Vec<Point2>) -> usize {
+    best_station_parallel(input, default_threads()).1
+}
+
+/// One thread per CPU that is available to the program (or a single one if that's unknown).
+pub fn default_threads() -> usize {
+    std::thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+/// Random asteroid field of the given size where roughly `density_percent` of the positions hold an
+/// asteroid, in puzzle input format. The same seed always produces the same field.
+pub fn synthetic_field(width: usize, height: usize, density_percent: u64, seed: u64) -> String {
+    // xorshift; good enough for benchmarks and doesn't need a dependency
+    let mut state = seed.max(1);
+    let mut field = String::with_capacity((width + 1) * height);
+    for _ in 0..height {
+        for _ in 0..width {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            field.push(if state % 100 < density_percent { '#' } else { '.' });
+        }
+        field.push('\n');
+    }
+    field
+}
+
 
 fn distance(lhs: &Vector2, rhs: &Vector2) -> isize {
     let d = (rhs.0 - lhs.0, rhs.1 - lhs.1);
     d.0*d.0 + d.1*d.1
 }
 
-pub fn part2(input: &[Point2]) -> isize {
-    let (center,_) = best_part_1(input);
-    eprintln!("Installing laser on {:?}", center);
-    let mut freq = BTreeMap::new();
-    angular_frequency(center, input, &mut freq);
-    for (_, asteroids) in freq.iter_mut() {
-        asteroids.sort_by(|l,r| distance(center, l).cmp(&distance(center, r)).reverse())
+/// Station with the best view and the number of asteroids visible from there.
+pub fn best_station(input: &[Point2]) -> (Point2, usize) {
+    let (station, visible) = best_hashed(input);
+    (*station, visible)
+}
+
+/// An asteroid destroyed by the laser, during the `rotation`th (starting at 1) sweep of the laser.
+#[derive(PartialEq,Eq,Debug,Clone,Copy)]
+pub struct Vaporization {
+    pub asteroid: Point2,
+    pub rotation: usize,
+}
+
+/// Iterator over the asteroids in the order in which they are vaporized by a laser that starts
+/// pointing North and rotates clockwise, destroying only the closest asteroid in each direction per sweep.
+pub struct Vaporizations {
+    /// Asteroids per direction (in laser order), farthest first so that the closest can be popped.
+    queues: Vec<Vec<Point2>>,
+    next_queue: usize,
+    rotation: usize,
+    remaining: usize,
+}
+
+impl Vaporizations {
+    /// Number of asteroids that have not been vaporized yet.
+    pub fn remaining(&self) -> usize {
+        self.remaining
     }
-    let mut to_remove: Vec<Direction> = Vec::new();
-    let mut n = 0;
-    while !freq.is_empty() {
-        for (d, asteroids) in freq.iter_mut() {
-            let asteroid = asteroids.pop().expect("direction should have asteroids");
-            n += 1;
-            eprintln!("vaporized asteroid #{} {:?} in direction {:?}", n, asteroid, d);
-            if n == 200 {
-                return asteroid.0 * 100 + asteroid.1;
-            }
+}
 
-            if asteroids.is_empty() {
-                eprintln!("    no more asteroids in direction {:?}", d);
-                to_remove.push(d.clone());
-            }
+impl Iterator for Vaporizations {
+    type Item = Vaporization;
+
+    fn next(&mut self) -> Option<Vaporization> {
+        if self.remaining == 0 {
+            return None;
         }
-        for d in to_remove.drain(..) {
-            freq.remove(&d);
+        loop {
+            if self.next_queue == self.queues.len() {
+                self.queues.retain(|q| !q.is_empty());
+                self.next_queue = 0;
+                self.rotation += 1;
+            }
+            let queue = &mut self.queues[self.next_queue];
+            self.next_queue += 1;
+            if let Some(asteroid) = queue.pop() {
+                self.remaining -= 1;
+                return Some(Vaporization { asteroid, rotation: self.rotation });
+            }
         }
     }
 
-    panic!("There are not 200 asteroids to vaporize!");
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl ExactSizeIterator for Vaporizations {}
+
+/// Vaporization order for a laser installed at `station` (which doesn't need to be an asteroid).
+pub fn vaporize(input: &[Point2], station: Point2) -> Vaporizations {
+    let mut freq = BTreeMap::new();
+    angular_frequency(&station, input, &mut freq);
+    let queues: Vec<Vec<Point2>> = freq.into_values().map(|mut asteroids| {
+        asteroids.sort_by(|l,r| distance(&station, l).cmp(&distance(&station, r)).reverse());
+        asteroids
+    }).collect();
+    let remaining = queues.iter().map(|q| q.len()).sum();
+    Vaporizations { next_queue: queues.len(), queues, rotation: 0, remaining }
+}
+
+/// The `n`th (starting at 1) asteroid vaporized from the best station, encoded as `x * 100 + y`.
+pub fn nth_vaporized(input: &[Point2], n: usize) -> Option<isize> {
+    let (station, _) = best_station(input);
+    n.checked_sub(1)
+        .and_then(|i| vaporize(input, station).nth(i))
+        .map(|v| v.asteroid.0 * 100 + v.asteroid.1)
+}
+
+#[aoc(day10, part2)]
+pub fn part2(input: &Vec<Point2>) -> isize {
+    nth_vaporized(input, 200).expect("There are not 200 asteroids to vaporize!")
 }
 
 #[cfg(test)]
@@ -223,40 +270,76 @@ mod tests {
 
     #[test]
     fn direction_order() {
-        let mut set = BTreeSet::new();
-        set.insert(Direction::South);
-        set.insert(Direction::East);
-        set.insert(Direction::West);
-        set.insert(Direction::North);
-        set.insert(Direction::NorthWest(-1,-2));
-        set.insert(Direction::NorthWest(-2,-1));
-        set.insert(Direction::SouthWest(-2,1));
-        set.insert(Direction::SouthWest(-1,1));
-        set.insert(Direction::NorthEast(4,-3));
-        set.insert(Direction::NorthEast(3,-4));
-        set.insert(Direction::SouthEast(1,1));
-        set.insert(Direction::SouthEast(1,2));
-        set.insert(Direction::SouthEast(2,1));
-
-        let sorted : Vec<Direction> = set.into_iter().collect();
-        assert_eq!(sorted, vec![
-            Direction::North,
-            Direction::NorthEast(3,-4),
-            Direction::NorthEast(4,-3),
-            Direction::East,
-            Direction::SouthEast(2,1),
-            Direction::SouthEast(1,1),
-            Direction::SouthEast(1,2),
-            Direction::South,
-            Direction::SouthWest(-1,1),
-            Direction::SouthWest(-2,1),
-            Direction::West,
-            Direction::NorthWest(-2,-1),
-            Direction::NorthWest(-1,-2)
+        let vectors = vec![(0,5), (1,0), (-3,0), (0,-1), (-1,-2), (-2,-1), (-2,1), (-1,1), (4,-3), (3,-4), (1,1), (1,2), (2,1), (2,2)];
+        let set: BTreeSet<Direction> = vectors.into_iter().map(|v| Direction::of(v).unwrap()).collect();
 
+        let sorted : Vec<(Scalar,Scalar)> = set.into_iter().map(|d| (d.x, d.y)).collect();
+        assert_eq!(sorted, vec![
+            (0,-1),
+            (3,-4),
+            (4,-3),
+            (1,0),
+            (2,1),
+            (1,1),
+            (1,2),
+            (0,1),
+            (-1,1),
+            (-2,1),
+            (-1,0),
+            (-2,-1),
+            (-1,-2)
         ]);
     }
 
+    #[test]
+    fn direction_of_origin() {
+        assert_eq!(Direction::of((0,0)), None);
+        assert_eq!(Direction::of((-6,4)), Some(Direction { x: -3, y: 2 }));
+    }
+
+    /// Clockwise angle from North in [0, 2π)
+    fn clockwise_angle((x,y): Vector2) -> f64 {
+        let a = (x as f64).atan2(-y as f64);
+        if a < 0.0 { a + 2.0 * std::f64::consts::PI } else { a }
+    }
+
+    fn assert_matches_atan2(a: Vector2, b: Vector2) {
+        let (da, db) = (Direction::of(a).unwrap(), Direction::of(b).unwrap());
+        let (aa, ab) = (clockwise_angle(a), clockwise_angle(b));
+        let expected = if (aa - ab).abs() < 1e-12 { Ordering::Equal } else { aa.partial_cmp(&ab).unwrap() };
+        assert_eq!(da.cmp(&db), expected, "{:?} vs {:?} (atan2: {} vs {})", a, b, aa, ab);
+    }
+
+    #[test]
+    fn direction_order_matches_atan2_on_grid() {
+        let vectors: Vec<Vector2> = (-6..=6).cartesian_product(-6..=6).filter(|v| *v != (0,0)).collect();
+        for a in &vectors {
+            for b in &vectors {
+                assert_matches_atan2(*a, *b);
+            }
+        }
+    }
+
+    #[test]
+    fn direction_order_matches_atan2_random() {
+        // xorshift, so that the test is reproducible without pulling in a dependency
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state % 2001) as Scalar - 1000
+        };
+        for _ in 0..10_000 {
+            let a = (next(), next());
+            let b = (next(), next());
+            if a != (0,0) && b != (0,0) {
+                assert_matches_atan2(a, b);
+                assert_matches_atan2(a, (a.0 * 3, a.1 * 3));
+            }
+        }
+    }
+
     #[test]
     fn visible_from_1() {
         let field = generator(".#..#
@@ -409,6 +492,74 @@ mod tests {
         assert_eq!(visible_from(&(11,  13), &field), 210);
     }
 
+    #[test]
+    fn optimized_matches_sorted() {
+        for seed in 1..=5 {
+            let field = generator(&synthetic_field(30, 20, 10 + seed * 7, seed));
+            let (station, visible) = best_part_1(&field);
+            assert_eq!(best_hashed(&field), (station, visible), "seed {}", seed);
+            assert_eq!(best_station_parallel(&field, 3), (*station, visible), "seed {}", seed);
+        }
+    }
+
+    #[test]
+    fn synthetic_field_is_reproducible() {
+        let field = synthetic_field(7, 3, 50, 42);
+        assert_eq!(field.lines().count(), 3);
+        assert!(field.lines().all(|l| l.len() == 7));
+        assert_eq!(field, synthetic_field(7, 3, 50, 42));
+        assert_ne!(field, synthetic_field(7, 3, 50, 43));
+    }
+
+    #[test]
+    fn vaporize_small_example() {
+        let field = generator(".#....#####...#..
+##...##.#####..##
+##...#...#.#####.
+..#.....X...###..
+..#.#.....#....##");
+        let order: Vec<Vaporization> = vaporize(&field, (8,3)).collect();
+        let first: Vec<Point2> = order.iter().take(9).map(|v| v.asteroid).collect();
+        assert_eq!(first, vec![(8,1), (9,0), (9,1), (10,0), (9,2), (11,1), (12,1), (11,2), (15,1)]);
+        // the marked station is an asteroid too, but isn't vaporized
+        assert_eq!(order.len(), field.len() - 1);
+        assert_eq!(order.last(), Some(&Vaporization { asteroid: (14,3), rotation: 3 }));
+    }
+
+    #[test]
+    fn vaporize_example4() {
+        let field = generator(".#..##.###...#######
+##.############..##.
+.#.######.########.#
+.###.#######.####.#.
+#####.##.#.##.###.##
+..#####..#.#########
+####################
+#.####....###.#.#.##
+##.#################
+#####.##.###..####..
+..######..##.#######
+####.##.####...##..#
+.#####..#.######.###
+##...#.##########...
+#.##########.#######
+.####.#.###.###.#.##
+....##.##.###..#####
+.#.#.###########.###
+#.#.#.#####.####.###
+###.##.####.##.#..##
+");
+        let (station, _) = best_station(&field);
+        assert_eq!(station, (11,13));
+        let order: Vec<Point2> = vaporize(&field, station).map(|v| v.asteroid).collect();
+        assert_eq!(order.len(), 299);
+        for (n, expected) in vec![(1, (11,12)), (2, (12,1)), (3, (12,2)), (10, (12,8)), (20, (16,0)), (50, (16,9)), (100, (10,16)), (199, (9,6)), (200, (8,2)), (201, (10,9)), (299, (11,1))] {
+            assert_eq!(order[n - 1], expected, "vaporization #{}", n);
+        }
+        assert_eq!(nth_vaporized(&field, 300), None);
+        assert_eq!(nth_vaporized(&field, 0), None);
+    }
+
     #[test]
     fn part2_example4() {
         let field = generator(".#..##.###...#######
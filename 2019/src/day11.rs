@@ -4,6 +4,8 @@ use serde::export::Formatter;
 use serde::export::fmt::Error;
 use itertools::Itertools;
 use crate::intcode::{InputDevice, OutputDevice, Hal};
+use std::io::{self, Write};
+use std::iter::once;
 
 #[aoc_generator(day11)]
 pub fn generator(input: &str) -> Vec<isize> {
@@ -202,7 +204,7 @@ fn add(lhs: &Vector2, rhs: &Vector2) -> Vector2 {
 }
 
 #[derive(Copy,Eq,PartialEq,Debug,Clone)]
-enum Color {
+pub enum Color {
     Black,
     White
 }
@@ -248,16 +250,165 @@ fn rotate_counter_clockwise(v: Vector2) -> Vector2 {
 }
 
 #[derive(Clone,Eq,PartialEq,Debug)]
-struct Hull {
+pub struct Hull {
     tiles: FxHashMap<Vector2, Color>,
     position: Vector2,
     speed: Vector2,
-    state: RobotState
+    state: RobotState,
+    /// Only recorded in animation mode (see `Hull::recording`)
+    trace: Option<Vec<Step>>
 }
+
+/// A single action of the robot, as recorded for animations
+#[derive(Copy,Clone,Eq,PartialEq,Debug)]
+enum Step {
+    Paint(Vector2, Color),
+    Move { position: Vector2, speed: Vector2 }
+}
+
 impl Hull {
+    /// A hull that records every paint and move step of the robot so that it can be animated.
+    pub fn recording(initial_color: Color) -> Hull {
+        let mut hull = Hull { trace: Some(Vec::new()), ..Hull::default() };
+        if initial_color != Color::Black {
+            hull.record(Step::Paint((0,0), initial_color));
+        }
+        hull
+    }
+
     fn color_at(&self, position: &Vector2) -> Color {
         *self.tiles.get(position).unwrap_or(&Color::Black)
     }
+
+    fn apply(&mut self, step: Step) {
+        match step {
+            Step::Paint(position, color) => { self.tiles.insert(position, color); },
+            Step::Move { position, speed } => {
+                self.position = position;
+                self.speed = speed;
+            }
+        }
+    }
+
+    fn record(&mut self, step: Step) {
+        self.apply(step);
+        if let Some(trace) = &mut self.trace {
+            trace.push(step);
+        }
+    }
+
+    fn paint_panel(&mut self, color: Color) {
+        self.record(Step::Paint(self.position, color));
+        self.state = RobotState::WaitingForDirection;
+    }
+
+    fn turn_and_move(&mut self, clockwise: bool) {
+        let speed = if clockwise { rotate_clockwise(self.speed) } else { rotate_counter_clockwise(self.speed) };
+        self.record(Step::Move { position: add(&self.position, &speed), speed });
+        self.state = RobotState::WaitingForColor;
+    }
+
+    /// Bounding box (inclusive) of all white panels.
+    fn white_bounds(&self) -> (Vector2, Vector2) {
+        bounds(self.tiles.iter().filter(|(_, c)| **c == Color::White).map(|(p, _)| *p))
+    }
+
+    /// Renders the white panels only (without the robot), cropped to the painted area.
+    pub fn render(&self) -> String {
+        let (min, max) = self.white_bounds();
+        let mut buf = String::new();
+        buf.push('\n');
+        for y in min.1..=max.1 {
+            for x in min.0..=max.0 {
+                buf.push(match self.color_at(&(x, y)) {
+                    Color::Black => ' ',
+                    Color::White => '\u{2588}'
+                });
+            }
+            buf.push('\n');
+        }
+        buf
+    }
+
+    /// Reads the registration identifier painted in white panels.
+    pub fn read_letters(&self) -> Option<String> {
+        let (min, max) = self.white_bounds();
+        let width = (max.0 - min.0 + 1) as usize;
+        let pixels: Vec<bool> = (min.1..=max.1)
+            .flat_map(|y| (min.0..=max.0).map(move |x| (x, y)))
+            .map(|p| self.color_at(&p) == Color::White)
+            .collect();
+        crate::ocr::recognize(&pixels, width)
+    }
+
+    /// Writes the white panels as a binary PBM (P4) image cropped to the painted area.
+    pub fn write_pbm(&self, out: &mut impl Write, scale: usize) -> io::Result<()> {
+        let (min, max) = self.white_bounds();
+        self.write_pbm_frame(out, min, max, scale, false)
+    }
+
+    /// PBM uses 1 for black ink, so a painted white panel is written as a 0 bit.
+    fn write_pbm_frame(&self, out: &mut impl Write, min: Vector2, max: Vector2, scale: usize, show_robot: bool) -> io::Result<()> {
+        let width = (max.0 - min.0 + 1) as usize * scale;
+        let height = (max.1 - min.1 + 1) as usize * scale;
+        write!(out, "P4\n{} {}\n", width, height)?;
+        let mut row = vec![0u8; width.div_ceil(8)];
+        for y in min.1..=max.1 {
+            for b in row.iter_mut() { *b = 0; }
+            for x in min.0..=max.0 {
+                let mut black = self.color_at(&(x, y)) == Color::Black;
+                if show_robot && (x, y) == self.position {
+                    black = !black;
+                }
+                if black {
+                    let offset = (x - min.0) as usize * scale;
+                    for px in offset..offset + scale {
+                        row[px / 8] |= 0x80 >> (px % 8);
+                    }
+                }
+            }
+            for _ in 0..scale {
+                out.write_all(&row)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Replays the recorded steps, yielding the state of the hull after each step.
+    pub fn frames(&self) -> impl Iterator<Item=Hull> + '_ {
+        let mut frame = Hull::default();
+        self.trace.iter().flatten().map(move |step| {
+            frame.apply(*step);
+            frame.clone()
+        })
+    }
+
+    /// Writes one PBM image per recorded step into a single multi-image PBM stream.
+    /// All frames share the same dimensions; the robot is drawn by inverting its panel.
+    pub fn write_animation_pbm(&self, out: &mut impl Write, scale: usize) -> io::Result<()> {
+        let (min, max) = bounds(once((0, 0)).chain(self.trace.iter().flatten().map(|step| match *step {
+            Step::Paint(position, _) => position,
+            Step::Move { position, .. } => position
+        })));
+        for frame in self.frames() {
+            frame.write_pbm_frame(out, min, max, scale, true)?;
+        }
+        Ok(())
+    }
+}
+
+fn bounds(positions: impl Iterator<Item=Vector2>) -> (Vector2, Vector2) {
+    let (mut min, mut max) = ((0, 0), (-1, -1));
+    for (i, p) in positions.enumerate() {
+        if i == 0 {
+            min = p;
+            max = p;
+        } else {
+            min = (min.0.min(p.0), min.1.min(p.1));
+            max = (max.0.max(p.0), max.1.max(p.1));
+        }
+    }
+    (min, max)
 }
 impl Display for Hull {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
@@ -290,7 +441,7 @@ impl Display for Hull {
 }
 impl Default for Hull {
     fn default() -> Self {
-        Hull { tiles: FxHashMap::default(), position: (0,0), speed: (0, -1), state: RobotState::WaitingForColor }
+        Hull { tiles: FxHashMap::default(), position: (0,0), speed: (0, -1), state: RobotState::WaitingForColor, trace: None }
     }
 }
 impl Iterator for &Hull {
@@ -317,25 +468,19 @@ fn paint_with(computer: &mut Computer, hull: &mut Hull) {
             (Yield::Halt, _) => return,
             (Yield::WaitForInput, _) => panic!("Input is always available."),
             (Yield::OutputReady(1), RobotState::WaitingForColor) => {
-                hull.tiles.insert(hull.position, Color::White);
-                hull.state = RobotState::WaitingForDirection;
+                hull.paint_panel(Color::White);
                 //eprintln!("painted white: \n{}", hull);
             },
             (Yield::OutputReady(0), RobotState::WaitingForColor) => {
-                hull.tiles.insert(hull.position, Color::Black);
-                hull.state = RobotState::WaitingForDirection;
+                hull.paint_panel(Color::Black);
                 //eprintln!("painted black: \n{}", hull);
             },
             (Yield::OutputReady(1), RobotState::WaitingForDirection) => {
-                hull.speed = rotate_clockwise(hull.speed);
-                hull.position = add(&hull.position, &hull.speed);
-                hull.state = RobotState::WaitingForColor;
+                hull.turn_and_move(true);
                 //eprintln!("turned + moved right: \n{}", hull);
             },
             (Yield::OutputReady(0), RobotState::WaitingForDirection) => {
-                hull.speed = rotate_counter_clockwise(hull.speed);
-                hull.position = add(&hull.position, &hull.speed);
-                hull.state = RobotState::WaitingForColor;
+                hull.turn_and_move(false);
                 //eprintln!("turned + moved left: \n{}", hull);
             },
             (Yield::Continue(_),_) => {},
@@ -344,17 +489,24 @@ fn paint_with(computer: &mut Computer, hull: &mut Hull) {
     }
 }
 async fn paint_async(program: &[isize], initial_color: Color) -> Hull {
-    let mut computer = crate::intcode::Computer::new(Vec::from(program));
     let mut hull = Hull::default();
     if initial_color != Color::Black {
         hull.tiles.insert((0,0), initial_color);
     }
+    paint_async_with(program, hull).await
+}
 
+async fn paint_async_with(program: &[isize], mut hull: Hull) -> Hull {
+    let mut computer = crate::intcode::Computer::new(Vec::from(program));
     computer.execute(&mut hull).await;
-
     hull
 }
 
+/// Paints the hull in animation mode, see `Hull::frames` and `Hull::write_animation_pbm`.
+pub fn paint_recording(program: &[isize], initial_color: Color) -> Hull {
+    async_std::task::block_on(paint_async_with(program, Hull::recording(initial_color)))
+}
+
 #[async_trait]
 impl InputDevice for Hull {
     async fn input(&mut self) -> isize {
@@ -365,19 +517,8 @@ impl InputDevice for Hull {
 impl OutputDevice for Hull {
     async fn output(&mut self, value: isize) -> () {
         match self.state {
-            RobotState::WaitingForColor => {
-                self.tiles.insert(self.position, Color::from(value));
-                self.state = RobotState::WaitingForDirection;
-            },
-            RobotState::WaitingForDirection => {
-                if value == 1 {
-                    self.speed = rotate_clockwise(self.speed);
-                } else {
-                    self.speed = rotate_counter_clockwise(self.speed);
-                }
-                self.position = add(&self.position, &self.speed);
-                self.state = RobotState::WaitingForColor;
-            }
+            RobotState::WaitingForColor => self.paint_panel(Color::from(value)),
+            RobotState::WaitingForDirection => self.turn_and_move(value == 1)
         }
     }
 }
@@ -396,20 +537,24 @@ pub fn part1_async(input: &Vec<isize>) -> usize {
 }
 
 #[aoc(day11, part2)]
-pub fn part2(input: &Vec<isize>) -> usize {
+pub fn part2(input: &Vec<isize>) -> String {
     let mut computer = Computer::new(input.clone());
     let mut hull = Hull::default();
     hull.tiles.insert((0,0), Color::White);
     paint_with(&mut computer, &mut hull);
-    //println!("{}", hull);
-    hull.tiles.len()
+    hull.read_letters().expect("registration identifier")
 }
 
 #[aoc(day11, part2, async)]
-pub fn part2_async(input: &Vec<isize>) -> usize {
+pub fn part2_async(input: &Vec<isize>) -> String {
     let hull = async_std::task::block_on(paint_async(&input[..], Color::White));
-    //println!("{}", hull);
-    hull.tiles.len()
+    hull.read_letters().expect("registration identifier")
+}
+
+#[aoc(day11, part2, render)]
+pub fn part2_render(input: &Vec<isize>) -> String {
+    let hull = async_std::task::block_on(paint_async(&input[..], Color::White));
+    hull.render()
 }
 
 #[cfg(test)]
@@ -447,4 +592,99 @@ mod tests {
         println!("Tiles: {:?}", hull.tiles);
         assert_eq!(hull.tiles.len(), 6);
     }
+
+    fn example1_program() -> Vec<isize> {
+        vec![104, 1, 104, 0, 104, 0, 104, 0, 104, 1, 104, 0, 104, 1, 104, 0, 104, 0, 104, 1, 104, 1, 104, 0, 104, 1, 104, 0, 99]
+    }
+
+    #[test]
+    fn render_example1() {
+        let hull = paint(&example1_program());
+        assert_eq!(hull.render(), "\n  \u{2588}\n  \u{2588}\n\u{2588}\u{2588} \n");
+    }
+
+    #[test]
+    fn pbm_example1() {
+        let hull = paint(&example1_program());
+        let mut buf = Vec::new();
+        hull.write_pbm(&mut buf, 1).expect("write to vec");
+        // 3x3 image, white panels are 0 bits
+        assert_eq!(buf, b"P4\n3 3\n\xc0\xc0\x20".to_vec());
+    }
+
+    #[test]
+    fn animation_example1() {
+        let hull = paint_recording(&example1_program(), Color::Black);
+        assert_eq!(hull.frames().count(), 14);
+        let last = hull.frames().last().expect("at least one frame");
+        assert_eq!(last.tiles, hull.tiles);
+        assert_eq!(last.position, hull.position);
+
+        let mut buf = Vec::new();
+        hull.write_animation_pbm(&mut buf, 2).expect("write to vec");
+        assert_eq!(buf.len(), 14 * ("P4\n6 6\n".len() + 6));
+    }
+
+    /// Registration identifier as painted by the robot: black panels around the letters and the
+    /// starting panel somewhere inside.
+    const HULL_LETTERS: &str = "\
+................
+.#..#.####.#...#
+.#..#.#....#...#
+.####.###...#.#.
+.#..#.#......#..
+.#..#.#......#..
+.#..#.####...#..
+................";
+
+    fn hull_from(art: &str, origin: Vector2) -> Hull {
+        let mut hull = Hull::default();
+        for (y, line) in art.lines().enumerate() {
+            for (x, c) in line.chars().enumerate() {
+                let color = if c == '#' { Color::White } else { Color::Black };
+                hull.tiles.insert((x as isize - origin.0, y as isize - origin.1), color);
+            }
+        }
+        hull
+    }
+
+    #[test]
+    fn read_letters_hull() {
+        let hull = hull_from(HULL_LETTERS, (1, 3));
+        assert_eq!(hull.color_at(&(0, 0)), Color::White);
+        assert_eq!(hull.read_letters(), Some("HEY".to_string()));
+        assert_eq!(hull_from(HULL_LETTERS, (-7, 20)).read_letters(), Some("HEY".to_string()));
+        // the three panels of the first example don't spell anything
+        assert_eq!(paint(&example1_program()).read_letters(), None);
+    }
+
+    /// A robot program that walks over the white panels of `art` (zig-zagging, as it has to turn
+    /// before every move) and paints every panel it passes in its color from `art`.
+    fn painting_program(art: &str, origin: Vector2) -> Vec<isize> {
+        let target = hull_from(art, origin);
+        let mut white: Vec<Vector2> = target.tiles.iter().filter(|(_, c)| **c == Color::White).map(|(p, _)| *p).collect();
+        white.sort_by_key(|p| (p.1, p.0));
+        let mut program = Vec::new();
+        let (mut position, mut speed) = ((0, 0), (0, -1));
+        for goal in white {
+            while position != goal {
+                let delta = if speed.1 != 0 { goal.0 - position.0 } else { goal.1 - position.1 };
+                let step = if delta < 0 { -1 } else { 1 };
+                let next = if speed.1 != 0 { (step, 0) } else { (0, step) };
+                let clockwise = rotate_clockwise(speed) == next;
+                program.extend(&[104, isize::from(target.color_at(&position)), 104, clockwise as isize]);
+                position = add(&position, &next);
+                speed = next;
+            }
+        }
+        program.extend(&[104, isize::from(target.color_at(&position)), 104, 0, 99]);
+        program
+    }
+
+    #[test]
+    fn part2_painted_letters() {
+        let program = painting_program(HULL_LETTERS, (1, 3));
+        assert_eq!(part2(&program), "HEY");
+        assert_eq!(part2_async(&program), "HEY");
+    }
 }
\ No newline at end of file
@@ -1,4 +1,6 @@
-use itertools::Itertools;
+use crate::number_theory::lcm_all;
+use std::io::{self, Write};
+use std::str::FromStr;
 
 type Vector3 = (isize, isize, isize);
 
@@ -7,8 +9,6 @@ pub fn generator(input: &str) -> Vec<Vector3> {
     input.lines().map(|l| serde_scan::scan!("<x={}, y={}, z={}>" <- l).expect("input")).collect()
 }
 
-
-
 #[inline(always)]
 fn apply_gravity(a: usize, b: usize, pos: &mut [isize], vel: &mut [isize]) -> () {
     let d = (pos[a] - pos[b]).signum();
@@ -20,171 +20,397 @@ fn apply_velocity(a: usize, pos: &mut [isize], vel: &mut [isize]) -> () {
     pos[a] += vel[a];
 }
 
-fn total_energy_after_steps(input: &[Vector3], steps: isize) -> isize {
-    let n = input.len();
-    let mut buf = vec![0isize; n * 6];
-    let (px, vx, py, vy, pz, vz) = split_dimensions(&mut buf);
-    initialize_pos(input, px, py, pz);
+/// Positions and velocities of all bodies in structure-of-arrays layout: one contiguous slice
+/// per dimension, i.e. `pos[d * n + a]` is the position of body `a` along dimension `d`.
+#[derive(Clone, Eq, PartialEq, Debug)]
+struct System {
+    n: usize,
+    pos: Vec<isize>,
+    vel: Vec<isize>,
+}
+
+impl System {
+    /// All bodies need to have the same number of dimensions. Bodies start at rest.
+    fn new<B: AsRef<[isize]>>(bodies: &[B]) -> System {
+        let n = bodies.len();
+        let dims = bodies.first().map_or(0, |b| b.as_ref().len());
+        let mut pos = vec![0isize; n * dims];
+        for (a, body) in bodies.iter().enumerate() {
+            let body = body.as_ref();
+            assert_eq!(body.len(), dims, "all bodies need to have the same number of dimensions");
+            for (d, x) in body.iter().enumerate() {
+                pos[d * n + a] = *x;
+            }
+        }
+        System { n, vel: vec![0isize; pos.len()], pos }
+    }
+
+    fn dims(&self) -> usize {
+        self.pos.len().checked_div(self.n).unwrap_or(0)
+    }
+
+    fn dimension(&self, d: usize) -> (&[isize], &[isize]) {
+        let range = d * self.n..(d + 1) * self.n;
+        (&self.pos[range.clone()], &self.vel[range])
+    }
 
-    fn total_energy(px: &mut [isize], vx: &mut [isize], py: &mut [isize], vy: &mut [isize], pz: &mut [isize], vz: &mut [isize]) -> isize {
-        (0usize..px.len()).map(|a| (px[a].abs() + py[a].abs() + pz[a].abs()) * (vx[a].abs() + vy[a].abs() + vz[a].abs())).sum()
+    fn time_step(&mut self) {
+        self.time_step_with(dim_time_step)
     }
 
-    #[allow(unused)]
-    for i in 1..=steps {
-        // Apply gravity
-        for (a,b) in &PAIRS {
-            apply_gravity(*a, *b, px, vx);
-            apply_gravity(*a, *b, py, vy);
-            apply_gravity(*a, *b, pz, vz);
+    fn time_step_with(&mut self, step: DimStep) {
+        if self.n == 0 {
+            return;
         }
+        for (p, v) in self.pos.chunks_exact_mut(self.n).zip(self.vel.chunks_exact_mut(self.n)) {
+            step(p, v);
+        }
+    }
+
+    fn total_energy(&self) -> isize {
+        (0..self.n).map(|a| {
+            let potential: isize = (0..self.dims()).map(|d| self.pos[d * self.n + a].abs()).sum();
+            let kinetic: isize = (0..self.dims()).map(|d| self.vel[d * self.n + a].abs()).sum();
+            potential * kinetic
+        }).sum()
+    }
+}
+
+fn to_bodies(input: &[Vector3]) -> Vec<[isize; 3]> {
+    input.iter().map(|m| [m.0, m.1, m.2]).collect()
+}
+
+/// Parses bodies with any number of named coordinates, e.g. `<x=1, y=2>` or `<x=-1, y=0, z=2, w=5>`.
+pub fn parse_bodies(input: &str) -> Vec<Vec<isize>> {
+    input.lines()
+        .filter(|l| !l.trim().is_empty())
+        .map(|l| l.trim().trim_start_matches('<').trim_end_matches('>')
+            .split(',')
+            .map(|c| c.split_once('=').expect("coordinate name=value").1.trim().parse().expect("integer coordinate"))
+            .collect())
+        .collect()
+}
 
-        // Apply velocity
-        for a in 0usize..n {
-            apply_velocity(a, px, vx);
-            apply_velocity(a, py, vy);
-            apply_velocity(a, pz, vz);
+/// Position and velocity of a single body.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct Moon {
+    pub position: Vec<isize>,
+    pub velocity: Vec<isize>,
+}
+
+/// Complete state of the system after `step` time steps.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct State {
+    pub step: usize,
+    pub moons: Vec<Moon>,
+}
+
+/// Endless iterator over the states of a system, starting with the initial state (step 0).
+pub struct Simulation {
+    system: System,
+    step: usize,
+}
+
+impl Simulation {
+    pub fn new<B: AsRef<[isize]>>(bodies: &[B]) -> Simulation {
+        Simulation { system: System::new(bodies), step: 0 }
+    }
+}
+
+impl Iterator for Simulation {
+    type Item = State;
+
+    fn next(&mut self) -> Option<State> {
+        let System { n, pos, vel } = &self.system;
+        let dims = self.system.dims();
+        let moons = (0..*n).map(|a| Moon {
+            position: (0..dims).map(|d| pos[d * n + a]).collect(),
+            velocity: (0..dims).map(|d| vel[d * n + a]).collect(),
+        }).collect();
+        let state = State { step: self.step, moons };
+        self.system.time_step();
+        self.step += 1;
+        Some(state)
+    }
+}
+
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum TrajectoryFormat {
+    /// One row per moon and step: `step,moon,x,y,z,vx,vy,vz`
+    Csv,
+    /// One JSON object per step: `{"step":0,"moons":[{"position":[..],"velocity":[..]},..]}`
+    JsonLines,
+}
+
+impl FromStr for TrajectoryFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(TrajectoryFormat::Csv),
+            "jsonl" | "json-lines" => Ok(TrajectoryFormat::JsonLines),
+            other => Err(format!("unknown trajectory format '{}', expected csv or jsonl", other))
         }
+    }
+}
 
-//        if (i % (steps / 10)) == 0 {
-//            eprintln!("After {} steps:", i);
-//            for a in 0usize..n {
-//                eprintln!("pos=({}, {}, {}) vel=({}, {}, {})", px[a], py[a], pz[a], vx[a], vy[a], vz[a]);
-//            }
-//            eprintln!("total energy: {}\n", total_energy(px,py,pz, vx,vy, vz));
-//        }
+fn axis_name(d: usize, dims: usize) -> String {
+    const AXES: [&str; 4] = ["x", "y", "z", "w"];
+    if dims <= AXES.len() { AXES[d].to_string() } else { format!("d{}", d) }
+}
+
+fn json_array(xs: &[isize]) -> String {
+    format!("[{}]", xs.iter().map(|x| x.to_string()).collect::<Vec<_>>().join(","))
+}
+
+/// Writes the states to `out` in the given format. For CSV, the header is derived from the first state.
+pub fn write_trajectory<W: Write, I: IntoIterator<Item=State>>(out: &mut W, format: TrajectoryFormat, states: I) -> io::Result<()> {
+    let mut header_written = false;
+    for state in states {
+        match format {
+            TrajectoryFormat::Csv => {
+                if !header_written {
+                    let dims = state.moons.first().map_or(0, |m| m.position.len());
+                    let mut columns = vec!["step".to_string(), "moon".to_string()];
+                    columns.extend((0..dims).map(|d| axis_name(d, dims)));
+                    columns.extend((0..dims).map(|d| format!("v{}", axis_name(d, dims))));
+                    writeln!(out, "{}", columns.join(","))?;
+                    header_written = true;
+                }
+                for (i, moon) in state.moons.iter().enumerate() {
+                    let values = moon.position.iter().chain(moon.velocity.iter()).map(|x| x.to_string()).collect::<Vec<_>>();
+                    writeln!(out, "{},{},{}", state.step, i, values.join(","))?;
+                }
+            }
+            TrajectoryFormat::JsonLines => {
+                let moons = state.moons.iter()
+                    .map(|m| format!("{{\"position\":{},\"velocity\":{}}}", json_array(&m.position), json_array(&m.velocity)))
+                    .collect::<Vec<_>>();
+                writeln!(out, "{{\"step\":{},\"moons\":[{}]}}", state.step, moons.join(","))?;
+            }
+        }
     }
+    Ok(())
+}
 
-    // Calculate total energy
-    total_energy(px, vx, py, vy, pz, vz)
+/// Total energy of the system after simulating `steps` time steps.
+pub fn energy_after_steps<B: AsRef<[isize]>>(bodies: &[B], steps: usize) -> isize {
+    energy_after_steps_with(bodies, steps, dim_time_step)
 }
 
-fn initialize_pos(input: &[Vector3], px: &mut [isize], py: &mut [isize], pz: &mut [isize]) {
-    for (i, moon) in input.into_iter().enumerate() {
-        px[i] = moon.0;
-        py[i] = moon.1;
-        pz[i] = moon.2;
+fn energy_after_steps_with<B: AsRef<[isize]>>(bodies: &[B], steps: usize, step: DimStep) -> isize {
+    let mut system = System::new(bodies);
+    for _ in 0..steps {
+        system.time_step_with(step);
     }
+    system.total_energy()
 }
 
+fn total_energy_after_steps(input: &[Vector3], steps: usize) -> isize {
+    energy_after_steps(&to_bodies(input), steps)
+}
 
 #[aoc(day12, part1)]
 pub fn part1(input: &Vec<Vector3>) -> isize {
     total_energy_after_steps(&input[..], 1000)
 }
 
-static PAIRS : [(usize,usize); 6] = [(0,1), (0,2), (0,3), (1,2),(1,3),(2,3)];
+#[aoc(day12, part1, vectorized)]
+pub fn part1_vectorized(input: &Vec<Vector3>) -> isize {
+    energy_after_steps_with(&to_bodies(input), 1000, dim_time_step_packed)
+}
 
-const N: usize = 4;
-//noinspection NonAsciiCharacters
 #[aoc(day12, part2)]
-pub fn part2(input: &Vec<Vector3>) -> String {
-    if input.len() != N {
-        panic!("only {} moons supported", N);
-    }
-    let init_x = input.iter().map(|m|m.0).collect::<Vec<_>>();
-    let init_y = input.iter().map(|m|m.1).collect::<Vec<_>>();
-    let init_z = input.iter().map(|m|m.2).collect::<Vec<_>>();
-    let mut hpx = [0isize; 4];
-    let mut hvx = [0isize; 4];
-    let mut hpy = [0isize; 4];
-    let mut hvy = [0isize; 4];
-    let mut hpz = [0isize; 4];
-    let mut hvz = [0isize; 4];
-    let mut tpx = [0isize; 4];
-    let mut tvx = [0isize; 4];
-    let mut tpy = [0isize; 4];
-    let mut tvy = [0isize; 4];
-    let mut tpz = [0isize; 4];
-    let mut tvz = [0isize; 4];
-    initialize_pos(input, &mut hpx, &mut hpy, &mut hpz);
-    initialize_pos(input, &mut tpx, &mut tpy, &mut tpz);
-
-    let (x_mu, x_lam) = dim_find_cycle(&init_x[..], &mut hpx, &mut hvx, &mut tpx, &mut tvx);
-    let (y_mu, y_lam) = dim_find_cycle(&init_y[..], &mut hpy, &mut hvy, &mut tpy, &mut tvy);
-    let (z_mu, z_lam) = dim_find_cycle(&init_z[..], &mut hpz, &mut hvz, &mut tpz, &mut tvz);
-
-    // I can't be asked to implement prime factorization and LCM for three integers.
-    // To determine the minimal number of steps, we have to perform the μ unique steps (max) and
-    // then on top of that the LCM of the λ (phase).
-    // Wolfram|Alpha can easily do this with a query like:
-    // "least common multiple of 22958, 286332, 231614"
-    // https://www.wolframalpha.com/input/?i=least+common+multiple+of+22958%2C+286332%2C+231614
-    format!("{:?}", (x_mu.max(y_mu).max(z_mu), (x_lam, y_lam, z_lam)))
-}
-
-fn dim_find_cycle(input: &[isize], mut hp: &mut [isize; N], mut hv: &mut [isize; N], mut tp: &mut [isize; N], mut tv: &mut [isize; N]) -> (isize, isize) {
+pub fn part2(input: &Vec<Vector3>) -> u128 {
+    steps_until_repeat(&to_bodies(input))
+}
+
+/// About a quarter faster than `part2` on the puzzle input, see `benches/day12.rs`.
+#[aoc(day12, part2, vectorized)]
+pub fn part2_vectorized(input: &Vec<Vector3>) -> u128 {
+    steps_until_repeat_vectorized(&to_bodies(input))
+}
+
+/// Number of steps until the system reaches a state that it has been in before.
+pub fn steps_until_repeat<B: AsRef<[isize]>>(bodies: &[B]) -> u128 {
+    steps_until_repeat_with(bodies, |p, v| dim_find_cycle(p, v, dim_time_step))
+}
+
+/// Same as `steps_until_repeat`, but steps the bodies in packed lanes (see `packed_find_cycle`).
+pub fn steps_until_repeat_vectorized<B: AsRef<[isize]>>(bodies: &[B]) -> u128 {
+    steps_until_repeat_with(bodies, packed_find_cycle)
+}
+
+/// Finds (mu, lambda) for the initial positions and velocities along one dimension.
+type DimCycle = fn(&[isize], &[isize]) -> (isize, isize);
+
+fn steps_until_repeat_with<B: AsRef<[isize]>>(bodies: &[B], dim_cycle: DimCycle) -> u128 {
+    // The dimensions are independent of each other, so we can find a cycle for each of them separately.
+    let system = System::new(bodies);
+    let cycles: Vec<(isize, isize)> = (0..system.dims()).map(|d| {
+        let (p, v) = system.dimension(d);
+        dim_cycle(p, v)
+    }).collect();
+
+    // To determine the minimal number of steps, we have to perform the mu unique steps (max) and
+    // then on top of that the LCM of the lambda (phase).
+    let mu = cycles.iter().map(|c| c.0).max().unwrap_or(0) as u128;
+    let lam = lcm_all(cycles.iter().map(|c| c.1 as u128)).expect("number of steps to fit into u128");
+    mu + lam
+}
+
+fn dim_find_cycle(init_p: &[isize], init_v: &[isize], dim_time_step: DimStep) -> (isize, isize) {
+    find_cycle(&(init_p.to_vec(), init_v.to_vec()), |(p, v)| dim_time_step(p, v))
+}
+
+fn find_cycle<S: Clone + Eq>(init: &S, time_step: impl Fn(&mut S)) -> (isize, isize) {
+    try_find_cycle(init, |s| {
+        time_step(s);
+        Some(())
+    }).expect("time step cannot fail")
+}
+
+/// Same as `find_cycle`, but gives up as soon as a time step fails.
+fn try_find_cycle<S: Clone + Eq>(init: &S, time_step: impl Fn(&mut S) -> Option<()>) -> Option<(isize, isize)> {
+    let mut hare = init.clone();
+    let mut tortoise = init.clone();
 
 // https://en.wikipedia.org/wiki/Cycle_detection#Floyd's_Tortoise_and_Hare
 // Step 1: find a repetition (might not be the first one)
 // hare advances twice as fast as the tortoise
-    dim_time_step(&mut tp, &mut tv);
-    dim_time_step(&mut hp, &mut hv);
-    dim_time_step(&mut hp, &mut hv);
-    while !dim_eq(&hp, &tp, &hv, &tv) {
-        dim_time_step(&mut tp, &mut tv);
-        dim_time_step(&mut hp, &mut hv);
-        dim_time_step(&mut hp, &mut hv);
-    }
-// Step 2: find position μ of the first repetition
+    time_step(&mut tortoise)?;
+    time_step(&mut hare)?;
+    time_step(&mut hare)?;
+    while hare != tortoise {
+        time_step(&mut tortoise)?;
+        time_step(&mut hare)?;
+        time_step(&mut hare)?;
+    }
+// Step 2: find position mu of the first repetition
 // tortoise starts at beginning, hare continues in lockstep within cycle
     let mut mu = 0isize;
-    for e in tv.iter_mut() { *e = 0; }
-    for (i, moon) in input.into_iter().enumerate() {
-        tp[i] = *moon;
-    }
-    while !dim_eq(&hp, &tp, &hv, &tv) {
-        dim_time_step(&mut tp, &mut tv);
-        dim_time_step(&mut hp, &mut hv);
+    tortoise = init.clone();
+    while hare != tortoise {
+        time_step(&mut tortoise)?;
+        time_step(&mut hare)?;
         mu += 1;
     }
-// Step 3: find length of the shortest cycle starting from state μ
+// Step 3: find length of the shortest cycle starting from state mu
     let mut lam = 1isize;
 // set hare to the state of tortoise
-    for (h, t) in hp.iter_mut().zip_eq(tp.iter()) {
-        *h = *t
+    hare = tortoise.clone();
+    time_step(&mut hare)?;
+    while hare != tortoise {
+        time_step(&mut hare)?;
+        lam += 1;
     }
-    for (h, t) in hv.iter_mut().zip_eq(tv.iter()) {
-        *h = *t
+    Some((mu, lam))
+}
+
+/// Advances the positions and velocities of all bodies along one dimension by one time step.
+type DimStep = fn(&mut [isize], &mut [isize]);
+
+fn dim_time_step(p: &mut [isize], v: &mut [isize]) {
+    let n = p.len();
+
+    // apply gravity
+    for a in 0..n {
+        for b in (a + 1)..n {
+            apply_gravity(a, b, p, v);
+        }
     }
-    dim_time_step(&mut hp, &mut hv);
-    while !dim_eq(&hp, &tp, &hv, &tv) {
-        dim_time_step(&mut hp, &mut hv);
-        lam += 1;
+
+    // apply velocity
+    for a in 0..n {
+        apply_velocity(a, p, v);
     }
-    (mu, lam)
 }
 
-fn dim_eq(hp: &[isize; N], tp: &[isize; N], hv: &[isize; N], tv: &[isize; N]) -> bool {
-    hp == tp && hv == tv
+const LANES: usize = 4;
+
+#[inline(always)]
+fn pull(from: isize, to: isize) -> isize {
+    (to > from) as isize - (to < from) as isize
 }
 
-fn dim_time_step(p: &mut [isize; 4], v: &mut [isize; 4]) -> () {
+/// Same as `dim_time_step`, but organized so that the compiler can use packed SIMD instructions:
+/// instead of visiting pairs of bodies, every body is compared with the body `k` places further
+/// along (wrapping around) for each offset `k`. Both halves of that rotation are contiguous slices,
+/// so each pass is a straight element-wise loop without any scattered velocity updates.
+fn dim_time_step_packed(p: &mut [isize], v: &mut [isize]) {
+    let n = p.len();
+
     // apply gravity
-    apply_gravity(0, 1, p, v);
-    apply_gravity(2, 3, p, v);
-    apply_gravity(1, 2, p, v);
-    apply_gravity(0, 3, p, v);
-    apply_gravity(0, 2, p, v);
-    apply_gravity(1, 3, p, v);
+    for k in 1..n {
+        let (v_head, v_tail) = v.split_at_mut(n - k);
+        for ((va, pa), pb) in v_head.iter_mut().zip(&p[..n - k]).zip(&p[k..]) {
+            *va += pull(*pa, *pb);
+        }
+        for ((va, pa), pb) in v_tail.iter_mut().zip(&p[n - k..]).zip(&p[..k]) {
+            *va += pull(*pa, *pb);
+        }
+    }
 
     // apply velocity
-    for a in 0..N {
-        apply_velocity(a, p, v);
+    for (pa, va) in p.iter_mut().zip(v.iter()) {
+        *pa += *va;
     }
 }
 
+/// Positions and velocities of exactly `LANES` bodies along one dimension, packed into `i64` lanes
+/// so that a whole state fits into a pair of 256 bit registers.
+type Lanes = ([i64; LANES], [i64; LANES]);
+
+/// Largest magnitude of a starting position or velocity in `Lanes`.
+const LANE_LIMIT: i64 = 1 << 31;
 
-fn split_dimensions(buf: &mut Vec<isize>) -> (&mut [isize], &mut [isize], &mut [isize], &mut [isize], &mut [isize], &mut [isize]) {
-    let (px, buf) = buf.split_at_mut(N);
-    let (vx, buf) = buf.split_at_mut(N);
-    let (py, buf) = buf.split_at_mut(N);
-    let (vy, buf) = buf.split_at_mut(N);
-    let (pz, vz) = buf.split_at_mut(N);
-    debug_assert_eq!(vz.len(), N);
-    (px, vx, py, vy, pz, vz)
+/// Number of time steps `Lanes` can take without overflowing: velocities change by less than `LANES` per
+/// step, so after t steps no magnitude exceeds `LANE_LIMIT + t * (LANE_LIMIT + LANES * t)` < 2^63.
+const MAX_LANE_STEPS: u64 = 1 << 30;
+
+/// Steps the lanes with plain (vectorizable) arithmetic.
+fn lanes_time_step((p, v): &mut Lanes) {
+    // broadcast each position against all lanes; a body doesn't pull on itself (signum of 0)
+    let before = *p;
+    for other in before.iter() {
+        for lane in 0..LANES {
+            v[lane] += (other - before[lane]).signum();
+        }
+    }
+    for lane in 0..LANES {
+        p[lane] += v[lane];
+    }
 }
 
+/// Cycle detection using packed lanes for the common case of `LANES` bodies, `dim_time_step_packed` otherwise
+/// (including when the lanes would need more than `MAX_LANE_STEPS`).
+fn packed_find_cycle(init_p: &[isize], init_v: &[isize]) -> (isize, isize) {
+    fn pack(xs: &[isize]) -> Option<[i64; LANES]> {
+        let mut lanes = [0i64; LANES];
+        if xs.len() != LANES {
+            return None;
+        }
+        for (lane, x) in lanes.iter_mut().zip(xs) {
+            *lane = Some(*x as i64).filter(|x| x.abs() < LANE_LIMIT)?;
+        }
+        Some(lanes)
+    }
+    match (pack(init_p), pack(init_v)) {
+        (Some(p), Some(v)) => lanes_find_cycle(&(p, v), MAX_LANE_STEPS)
+            .unwrap_or_else(|| dim_find_cycle(init_p, init_v, dim_time_step_packed)),
+        _ => dim_find_cycle(init_p, init_v, dim_time_step_packed)
+    }
+}
+
+/// Cycle detection on `Lanes`, `None` if it would take more than `max_steps` time steps.
+fn lanes_find_cycle(init: &Lanes, max_steps: u64) -> Option<(isize, isize)> {
+    let steps = std::cell::Cell::new(0u64);
+    try_find_cycle(init, |lanes| {
+        steps.set(steps.get() + 1);
+        lanes_time_step(lanes);
+        if steps.get() <= max_steps { Some(()) } else { None }
+    })
+}
 
 #[cfg(test)]
 mod tests {
@@ -214,4 +440,145 @@ mod tests {
 <x=9, y=-8, z=-3>");
         assert_eq!(total_energy_after_steps(&input[..], 100), 1940);
     }
+
+    #[test]
+    fn part2_example1() {
+        let input = vec![(-1, 0, 2), (2, -10, -7), (4, -8, 8), (3, 5, -1)];
+        assert_eq!(part2(&input), 2772);
+    }
+
+    #[test]
+    fn part2_example2() {
+        let input = generator("<x=-8, y=-10, z=0>
+<x=5, y=5, z=10>
+<x=2, y=-7, z=3>
+<x=9, y=-8, z=-3>");
+        assert_eq!(part2(&input), 4686774924);
+    }
+
+    #[test]
+    fn packed_matches_scalar() {
+        for n in 1..=11 {
+            let mut p: Vec<isize> = (0..n).map(|x| (x * 7919) % 13 - 6).collect();
+            let mut v: Vec<isize> = (0..n).map(|x| x % 3 - 1).collect();
+            let (mut packed_p, mut packed_v) = (p.clone(), v.clone());
+            for _ in 0..50 {
+                dim_time_step(&mut p, &mut v);
+                dim_time_step_packed(&mut packed_p, &mut packed_v);
+                assert_eq!((&packed_p, &packed_v), (&p, &v), "{} bodies", n);
+            }
+        }
+    }
+
+    #[test]
+    fn simulation_states() {
+        let bodies = parse_bodies("<x=-1, y=0, z=2>
+<x=2, y=-10, z=-7>
+<x=4, y=-8, z=8>
+<x=3, y=5, z=-1>");
+        let states: Vec<State> = Simulation::new(&bodies).take(11).collect();
+        assert_eq!(states[0].step, 0);
+        assert_eq!(states[0].moons[1], Moon { position: vec![2, -10, -7], velocity: vec![0, 0, 0] });
+        // after 1 step: pos=<x= 3, y=-7, z=-4>, vel=<x= 1, y= 3, z= 3>
+        assert_eq!(states[1].moons[1], Moon { position: vec![3, -7, -4], velocity: vec![1, 3, 3] });
+        // after 10 steps: pos=<x= 2, y= 1, z=-3>, vel=<x=-3, y=-2, z= 1>
+        assert_eq!(states[10].moons[0], Moon { position: vec![2, 1, -3], velocity: vec![-3, -2, 1] });
+    }
+
+    #[test]
+    fn trajectory_csv() {
+        let mut out = Vec::new();
+        write_trajectory(&mut out, TrajectoryFormat::Csv, Simulation::new(&[[0, 1], [2, 1]]).take(2)).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "step,moon,x,y,vx,vy
+0,0,0,1,0,0
+0,1,2,1,0,0
+1,0,1,1,1,0
+1,1,1,1,-1,0
+");
+    }
+
+    #[test]
+    fn trajectory_json_lines() {
+        let mut out = Vec::new();
+        write_trajectory(&mut out, TrajectoryFormat::JsonLines, Simulation::new(&[[0], [2]]).skip(1).take(1)).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "{\"step\":1,\"moons\":[{\"position\":[1],\"velocity\":[1]},{\"position\":[1],\"velocity\":[-1]}]}\n");
+    }
+
+    #[test]
+    fn lanes_match_scalar() {
+        let (p, v) = ([-8, 5, 2, 9], [0; 4]);
+        assert_eq!(packed_find_cycle(&p, &v), dim_find_cycle(&p, &v, dim_time_step));
+        let (p, v) = ([1, -1, 4, 0, 2, -2, 3], [0; 7]);
+        assert_eq!(packed_find_cycle(&p, &v), dim_find_cycle(&p, &v, dim_time_step));
+    }
+
+    #[test]
+    fn lanes_step_budget() {
+        let mut lanes = ([-5, 5, 0, 0], [0; 4]);
+        lanes_time_step(&mut lanes);
+        assert_eq!(lanes, ([-2, 2, 0, 0], [3, -3, 0, 0]));
+        let cycle = dim_find_cycle(&[-8, 5, 2, 9], &[0; 4], dim_time_step);
+        let lanes = ([-8, 5, 2, 9], [0; 4]);
+        assert_eq!(lanes_find_cycle(&lanes, 10_000), Some(cycle));
+        assert_eq!(lanes_find_cycle(&lanes, 10), None);
+        // escaping bodies never repeat, the lanes give up long before they could overflow
+        assert_eq!(lanes_find_cycle(&([0, 1, 2, LANE_LIMIT - 1], [0, 0, 0, LANE_LIMIT - 1]), 1_000_000), None);
+        // positions that don't fit the lanes take the scalar path
+        let far = LANE_LIMIT as isize;
+        let (p, v) = ([-far, far, -far, far], [0; 4]);
+        assert_eq!(packed_find_cycle(&p, &v), dim_find_cycle(&p, &v, dim_time_step));
+    }
+
+    #[test]
+    fn part2_vectorized_example2() {
+        let input = generator("<x=-8, y=-10, z=0>
+<x=5, y=5, z=10>
+<x=2, y=-7, z=3>
+<x=9, y=-8, z=-3>");
+        assert_eq!(part1_vectorized(&input), part1(&input));
+        assert_eq!(part2_vectorized(&input), 4686774924);
+    }
+
+    #[test]
+    fn parse_any_dimensions() {
+        assert_eq!(parse_bodies("<x=1, y=-2>\n<x=3, y=4>\n"), vec![vec![1, -2], vec![3, 4]]);
+        assert_eq!(parse_bodies("<x=-1, y=0, z=2, w=7>"), vec![vec![-1, 0, 2, 7]]);
+    }
+
+    #[test]
+    fn three_moons_in_four_dimensions() {
+        let bodies = vec![[0, 0, 0, 1], [2, 0, 1, 0], [-3, 4, 0, 0]];
+        // simulate the cycle and check that the state actually repeats
+        let steps = steps_until_repeat(&bodies);
+        assert_eq!(steps, 136);
+        let mut system = System::new(&bodies);
+        let initial = system.clone();
+        for _ in 0..steps {
+            system.time_step();
+        }
+        assert_eq!(system, initial);
+    }
+
+    #[test]
+    fn single_dimension_many_bodies() {
+        let bodies = vec![[1], [-1], [4], [0], [2], [-2], [3]];
+        let steps = steps_until_repeat(&bodies);
+        assert_eq!(steps, 4);
+        let mut system = System::new(&bodies);
+        let initial = system.clone();
+        for i in 1..=steps {
+            system.time_step();
+            assert_eq!(system == initial, i == steps, "first repetition after {} steps", steps);
+        }
+    }
+
+    #[test]
+    fn generalized_matches_example() {
+        let bodies = parse_bodies("<x=-1, y=0, z=2>
+<x=2, y=-10, z=-7>
+<x=4, y=-8, z=8>
+<x=3, y=5, z=-1>");
+        assert_eq!(energy_after_steps(&bodies, 10), 179);
+        assert_eq!(steps_until_repeat(&bodies), 2772);
+    }
 }
\ No newline at end of file
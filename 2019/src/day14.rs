@@ -1,31 +1,46 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{self, Write};
 use std::rc::Rc;
-use fxhash::FxHashMap;
+use fxhash::{FxHashMap, FxHashSet};
+use crate::dot::quote;
 
 #[aoc_generator(day14)]
-pub fn generator(input: &str) -> Vec<Recipe> {
+pub fn generator(input: &str) -> Result<Reactions, ReactionError> {
+    Reactions::new(parse_recipes(input)?)
+}
+
+/// Parses one recipe per line (blank lines are skipped), without checking how they fit together.
+pub fn parse_recipes(input: &str) -> Result<Vec<Recipe>, ReactionError> {
     let mut elements: FxHashMap<&str, Rc<String>> = FxHashMap::default();
     fn intern<'a>(elements: &mut FxHashMap<&'a str, Rc<String>>, id: &'a str) -> Rc<String> {
         elements.entry(id).or_insert_with(|| Rc::new(id.to_owned())).clone()
     }
-    fn parse_ingredient<'a>(elements: &mut FxHashMap<&'a str, Rc<String>>, raw: &'a str) -> Ingredient {
-        let t: (isize, &'_ str) = serde_scan::from_str(raw.trim()).expect("AMOUNT INGREDIENT");
-        Ingredient {
+    fn parse_ingredient<'a>(elements: &mut FxHashMap<&'a str, Rc<String>>, raw: &'a str) -> Result<Ingredient, String> {
+        let t: (isize, &'_ str) = serde_scan::from_str(raw.trim())
+            .map_err(|_| format!("expected AMOUNT CHEMICAL, found '{}'", raw.trim()))?;
+        if t.0 <= 0 {
+            return Err(format!("amount of {} must be positive", t.1));
+        }
+        Ok(Ingredient {
             amount: t.0,
             element: intern(elements, t.1)
-        }
+        })
     }
-    input.lines().map(|line| {
+    input.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()).map(|(i, line)| {
+        let unparseable = |reason: String| ReactionError::Unparseable { line: i + 1, content: line.to_owned(), reason };
         let mut parts = line.splitn(2, "=>");
-        let lhs : &'_ str = parts.next().expect("recipe must have lhs");
-        let rhs : &'_ str = parts.next().expect("recipe must have rhs").trim();
+        let lhs : &'_ str = parts.next().expect("splitn yields at least one part");
+        let rhs : &'_ str = parts.next().ok_or_else(|| unparseable("missing '=>'".to_owned()))?.trim();
         let ingredient_parts = lhs.split(',');
         let ingredients = ingredient_parts
             .map(|p| parse_ingredient(&mut elements, p))
-            .collect();
-        Recipe {
+            .collect::<Result<_, _>>()
+            .map_err(unparseable)?;
+        Ok(Recipe {
             input: ingredients,
-            output: parse_ingredient(&mut elements, rhs)
-        }
+            output: parse_ingredient(&mut elements, rhs).map_err(unparseable)?
+        })
     }).collect()
 }
 
@@ -40,100 +55,370 @@ pub struct Ingredient {
     element: Rc<String>,
     amount: isize
 }
-impl Ingredient {
-    fn times(&self, multiplier: isize) -> Ingredient {
-        Ingredient {
-            element: self.element.clone(),
-            amount: self.amount * multiplier
+
+#[derive(Debug,Eq,PartialEq,Clone)]
+pub enum ReactionError {
+    /// Line `line` (starting at 1) isn't a valid recipe.
+    Unparseable { line: usize, content: String, reason: String },
+    /// Chemicals that are needed, but not produced by any recipe (and aren't raw materials).
+    MissingRecipe(Vec<String>),
+    /// Chemicals that are produced by more than one recipe.
+    DuplicateProducer(Vec<String>),
+    /// Chemicals that (indirectly) need themselves, in the order in which they need each other.
+    Cycle(Vec<String>),
+    /// Chemicals that are produced, but never needed for the target.
+    Unreachable(Vec<String>),
+    /// A query referred to a chemical that isn't part of the reactions.
+    UnknownChemical(String),
+    /// A query needs more of a chemical than fits into an `isize`.
+    Overflow(String),
+}
+
+impl fmt::Display for ReactionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReactionError::Unparseable { line, content, reason } => write!(f, "line {}: {} in '{}'", line, reason, content),
+            ReactionError::MissingRecipe(chemicals) => write!(f, "no recipe produces {}", chemicals.join(", ")),
+            ReactionError::DuplicateProducer(chemicals) => write!(f, "more than one recipe produces {}", chemicals.join(", ")),
+            ReactionError::Cycle(chemicals) => write!(f, "reactions form a cycle: {} => {}", chemicals.join(" => "), chemicals[0]),
+            ReactionError::Unreachable(chemicals) => write!(f, "not needed for the target: {}", chemicals.join(", ")),
+            ReactionError::UnknownChemical(chemical) => write!(f, "unknown chemical {}", chemical),
+            ReactionError::Overflow(chemical) => write!(f, "amount of {} needed is too large", chemical),
         }
     }
 }
 
-#[aoc(day14, part1)]
-pub fn part1(input: &Vec<Recipe>) -> isize {
-    let recipies: FxHashMap<Rc<String>, &Recipe> = input.into_iter().map(|r| (r.output.element.clone(), r)).collect();
-    let mut needs : FxHashMap<Rc<String>, isize> = FxHashMap::default();
+impl std::error::Error for ReactionError {}
+
+/// Amounts of chemicals on hand, by name.
+pub type Inventory = BTreeMap<String, isize>;
 
-    needs.insert(Rc::new("FUEL".to_owned()), 1);
-    satisfy_needs(&recipies, &mut needs, &mut Vec::new());
-    *needs.get(&"ORE".to_owned()).expect("ORE needs")
+/// Reactions that form a DAG with a single recipe for every chemical that is needed for the target,
+/// apart from the raw materials.
+#[derive(Debug,Clone)]
+pub struct Reactions {
+    recipes: Vec<Recipe>,
+    /// Index into `recipes` by output chemical.
+    producers: FxHashMap<Rc<String>, usize>,
+    /// Chemicals that are only ever taken from the inventory. Recipes producing them never run.
+    raw_materials: FxHashSet<String>,
+    /// Every chemical needed for the target comes before all of its ingredients (including the raw materials).
+    order: Vec<Rc<String>>,
 }
 
-#[aoc(day14, part2)]
-pub fn part2(input: &Vec<Recipe>) -> isize {
-    eprintln!("input: {:?}", input);
-    let recipies: FxHashMap<Rc<String>, &Recipe> = input.into_iter().map(|r| (r.output.element.clone(), r)).collect();
-    let mut needs : FxHashMap<Rc<String>, isize> = FxHashMap::default();
-
-    let fuel_id = recipies.get_key_value(&"FUEL".to_owned()).expect("FUEL to be a recipe").0.clone();
-    let ore_id = Rc::new("ORE".to_owned());
-
-    needs.insert(ore_id.clone(), -1_000_000_000_000);
-    fn need_more_fuel(needs: &mut FxHashMap<Rc<String>, isize>, fuel_id: &Rc<String>) {
-        let required_amount = needs.entry(fuel_id.clone()).or_insert(0);
-        *required_amount += 1;
-    }
-    eprintln!("needs: {:?}", needs);
-
-    let mut fuel_produced = 0isize;
-    let mut buf = Vec::new();
-    while *needs.get(&ore_id).expect("we just inserted ore") < 0 {
-        need_more_fuel(&mut needs, &fuel_id);
-        satisfy_needs(&recipies, &mut needs, &mut buf);
-        fuel_produced += 1;
-        if fuel_produced % 50000 == 0 {
-            let ore_left = -*needs.get(&ore_id).expect("we just inserted ore");
-            eprintln!("fuel: {}, ore: {}, pct: {:.2}%", fuel_produced, ore_left, ore_left as f64/1_000_000_000_000.0*100.0);
+fn sorted_names<'a>(chemicals: impl Iterator<Item=&'a Rc<String>>) -> Vec<String> {
+    let mut names: Vec<String> = chemicals.map(|c| c.to_string()).collect();
+    names.sort();
+    names.dedup();
+    names
+}
+
+impl Reactions {
+    /// The puzzle's reactions: FUEL made from ORE.
+    pub fn new(recipes: Vec<Recipe>) -> Result<Reactions, ReactionError> {
+        Self::with_raw_materials(recipes, &["ORE"], "FUEL")
+    }
+
+    /// Reactions for making `target` (or anything it needs) out of `raw_materials`.
+    pub fn with_raw_materials(recipes: Vec<Recipe>, raw_materials: &[&str], target: &str) -> Result<Reactions, ReactionError> {
+        let raw_materials: FxHashSet<String> = raw_materials.iter().map(|&r| r.to_owned()).collect();
+        let mut producers: FxHashMap<Rc<String>, usize> = FxHashMap::default();
+        let mut duplicates = Vec::new();
+        for (i, recipe) in recipes.iter().enumerate() {
+            if producers.insert(recipe.output.element.clone(), i).is_some() {
+                duplicates.push(&recipe.output.element);
+            }
         }
+        if !duplicates.is_empty() {
+            return Err(ReactionError::DuplicateProducer(sorted_names(duplicates.into_iter())));
+        }
+
+        let target = Rc::new(target.to_owned());
+        let missing = recipes.iter()
+            .filter(|r| !raw_materials.contains(&r.output.element[..]))
+            .flat_map(|r| r.input.iter().map(|i| &i.element))
+            .chain(std::iter::once(&target))
+            .filter(|c| !raw_materials.contains(&c[..]) && !producers.contains_key(*c));
+        let missing = sorted_names(missing);
+        if !missing.is_empty() {
+            return Err(ReactionError::MissingRecipe(missing));
+        }
+
+        let order = Self::topological_order(&recipes, &producers, &raw_materials, &target)?;
+        let reachable: FxHashSet<&Rc<String>> = order.iter().collect();
+        let unreachable = sorted_names(producers.keys().filter(|c| !reachable.contains(c)));
+        if !unreachable.is_empty() {
+            return Err(ReactionError::Unreachable(unreachable));
+        }
+
+        Ok(Reactions { recipes, producers, raw_materials, order })
     }
 
-    let final_ore_need = *needs.get(&ore_id).expect("ORE needs");
-    if final_ore_need > 0 {
-        fuel_produced - 1
-    } else {
-        fuel_produced
+    /// Orders the chemicals needed for `target` so that every chemical comes before all of its
+    /// ingredients (reverse post-order of a depth first search). Raw materials are included, but not expanded.
+    fn topological_order(recipes: &[Recipe], producers: &FxHashMap<Rc<String>, usize>, raw_materials: &FxHashSet<String>,
+                         target: &Rc<String>) -> Result<Vec<Rc<String>>, ReactionError> {
+        #[derive(Clone,Copy,Eq,PartialEq)]
+        enum Mark { InProgress, Done }
+        let producers: FxHashMap<&Rc<String>, usize> = producers.iter()
+            .filter(|(c, _)| !raw_materials.contains(&c[..]))
+            .map(|(c, &i)| (c, i))
+            .collect();
+        fn visit(recipes: &[Recipe], producers: &FxHashMap<&Rc<String>, usize>, chemical: &Rc<String>,
+                 marks: &mut FxHashMap<Rc<String>, Mark>, path: &mut Vec<Rc<String>>, order: &mut Vec<Rc<String>>) -> Result<(), ReactionError> {
+            match marks.get(chemical) {
+                Some(Mark::Done) => return Ok(()),
+                Some(Mark::InProgress) => {
+                    let start = path.iter().position(|c| c == chemical).expect("chemical in progress is on the path");
+                    return Err(ReactionError::Cycle(path[start..].iter().map(|c| c.to_string()).collect()));
+                }
+                None => {}
+            }
+            marks.insert(chemical.clone(), Mark::InProgress);
+            path.push(chemical.clone());
+            if let Some(&i) = producers.get(chemical) {
+                for ingredient in &recipes[i].input {
+                    visit(recipes, producers, &ingredient.element, marks, path, order)?;
+                }
+            }
+            path.pop();
+            marks.insert(chemical.clone(), Mark::Done);
+            order.push(chemical.clone());
+            Ok(())
+        }
+        let mut order = Vec::new();
+        visit(recipes, &producers, target, &mut FxHashMap::default(), &mut Vec::new(), &mut order)?;
+        order.reverse();
+        Ok(order)
     }
-}
 
-fn satisfy_needs(recipies: &FxHashMap<Rc<String>, &Recipe>, needs: &mut FxHashMap<Rc<String>, isize>, buf: &mut Vec<Ingredient>) -> () {
-    fn num_applications(mut amount_needed: isize, recipe_output: isize) -> isize {
-        let mut num = 0isize;
-        while amount_needed > 0 {
-            num += 1;
-            amount_needed -= recipe_output;
+    pub fn recipes(&self) -> &[Recipe] {
+        &self.recipes
+    }
+
+    /// The recipe that produces `chemical`, `None` for raw materials.
+    fn recipe_for(&self, chemical: &Rc<String>) -> Option<&Recipe> {
+        match self.producers.get(chemical) {
+            Some(&i) if !self.raw_materials.contains(&chemical[..]) => Some(&self.recipes[i]),
+            _ => None
+        }
+    }
+
+    fn check_known(&self, chemical: &str) -> Result<(), ReactionError> {
+        if self.order.iter().any(|c| &c[..] == chemical) {
+            Ok(())
+        } else {
+            Err(ReactionError::UnknownChemical(chemical.to_owned()))
+        }
+    }
+
+    /// Runs the recipes just often enough to satisfy `needs`, adding the ingredients they consume.
+    /// Once all consumers of a chemical have been processed (guaranteed by the topological order),
+    /// its total need is known. Whatever is in `stock` is used up before running any recipe.
+    /// `on_run` is called with each recipe and how often it fires.
+    fn resolve<'a>(&'a self, needs: &mut FxHashMap<&'a str, isize>, stock: &Inventory, mut on_run: impl FnMut(&'a Recipe, isize)) -> Result<(), ReactionError> {
+        for chemical in &self.order {
+            let amount_needed = needs.get(&chemical[..]).cloned().unwrap_or(0);
+            let missing = amount_needed - stock.get(&chemical[..]).cloned().unwrap_or(0);
+            let recipe = match self.recipe_for(chemical) {
+                Some(recipe) if missing > 0 => recipe,
+                _ => continue
+            };
+            let num = (missing - 1) / recipe.output.amount + 1;
+            for ingredient in &recipe.input {
+                let need = needs.entry(&ingredient.element[..]).or_insert(0);
+                *need = num.checked_mul(ingredient.amount).and_then(|n| need.checked_add(n))
+                    .ok_or_else(|| ReactionError::Overflow(ingredient.element.to_string()))?;
+            }
+            on_run(recipe, num);
+        }
+        Ok(())
+    }
+
+    /// Raw materials needed to produce `amount` units of `target`, on top of what's in `stock`.
+    /// The stock may contain intermediate chemicals as well as raw materials. Raw materials that
+    /// the stock already covers are left out.
+    pub fn raw_materials_needed(&self, target: &str, amount: isize, stock: &Inventory) -> Result<Inventory, ReactionError> {
+        self.check_known(target)?;
+        let mut needs: FxHashMap<&str, isize> = FxHashMap::default();
+        needs.insert(target, amount);
+        self.resolve(&mut needs, stock, |_, _| ())?;
+        Ok(self.order.iter()
+            .filter(|c| self.recipe_for(c).is_none())
+            .filter_map(|c| {
+                let missing = needs.get(&c[..]).cloned().unwrap_or(0) - stock.get(&c[..]).cloned().unwrap_or(0);
+                if missing > 0 { Some((c.to_string(), missing)) } else { None }
+            })
+            .collect())
+    }
+
+    /// Largest amount of `target` that can be produced from `inventory` alone (binary search).
+    pub fn max_producible(&self, target: &str, inventory: &Inventory) -> Result<isize, ReactionError> {
+        let affordable = |amount| match self.raw_materials_needed(target, amount, inventory) {
+            Ok(needed) => Ok(needed.is_empty()),
+            // needing more than isize::MAX of anything is never affordable
+            Err(ReactionError::Overflow(_)) => Ok(false),
+            Err(e) => Err(e),
+        };
+        let mut low = 0;
+        let mut high = 1;
+        while affordable(high)? {
+            if high == isize::MAX {
+                return Ok(high);
+            }
+            low = high;
+            high = high.saturating_mul(2);
         }
-        num
+        // invariant: low is affordable, high isn't
+        while high - low > 1 {
+            let mid = low + (high - low) / 2;
+            if affordable(mid)? {
+                low = mid;
+            } else {
+                high = mid;
+            }
+        }
+        Ok(low)
+    }
+
+    /// ORE needed to produce `fuel` units of FUEL.
+    pub fn ore_for_fuel(&self, fuel: isize) -> isize {
+        self.raw_materials_needed("FUEL", fuel, &Inventory::new())
+            .expect("reactions produce FUEL")
+            .get("ORE").cloned().unwrap_or(0)
+    }
+
+    /// Everything that happens to produce `amount` units of `target`, which can be any chemical
+    /// that is needed for the target the reactions were built for.
+    pub fn plan(&self, target: &str, amount: isize) -> Result<ProductionPlan, ReactionError> {
+        self.check_known(target)?;
+        let mut needs: FxHashMap<&str, isize> = FxHashMap::default();
+        needs.insert(target, amount);
+        let mut runs: FxHashMap<&str, isize> = FxHashMap::default();
+        self.resolve(&mut needs, &Inventory::new(), |recipe, num| { runs.insert(&recipe.output.element[..], num); })?;
+
+        let steps = self.order.iter().filter_map(|chemical| {
+            let needed = needs.get(&chemical[..]).cloned().unwrap_or(0);
+            if needed <= 0 {
+                return None;
+            }
+            let production = match self.recipe_for(chemical) {
+                Some(recipe) => {
+                    let runs = runs.get(&chemical[..]).cloned().unwrap_or(0);
+                    // the last run can overshoot what's needed by almost a whole batch
+                    runs.checked_mul(recipe.output.amount)
+                        .and_then(|produced| Some((produced, produced.checked_sub(needed)?)))
+                        .map(|(produced, leftover)| Production { chemical: chemical.to_string(), runs: Some(runs), produced, needed, leftover })
+                        .ok_or_else(|| ReactionError::Overflow(chemical.to_string()))
+                }
+                None => Ok(Production { chemical: chemical.to_string(), runs: None, produced: 0, needed, leftover: 0 }),
+            };
+            Some(production)
+        }).collect::<Result<_, _>>()?;
+        Ok(ProductionPlan { target: target.to_owned(), amount, steps })
+    }
+
+    /// Largest amount of FUEL that needs at most `ore` ORE.
+    pub fn max_fuel(&self, ore: isize) -> isize {
+        let inventory: Inventory = std::iter::once(("ORE".to_owned(), ore)).collect();
+        self.max_producible("FUEL", &inventory).expect("reactions produce FUEL")
+    }
+}
+
+/// What happens to one chemical in a `ProductionPlan`.
+#[derive(Debug,Eq,PartialEq,Clone)]
+pub struct Production {
+    pub chemical: String,
+    /// How often the recipe producing the chemical fires, `None` for raw materials.
+    pub runs: Option<isize>,
+    pub produced: isize,
+    /// Amount consumed by other reactions plus the requested amount for the target.
+    pub needed: isize,
+    /// Produced but not needed.
+    pub leftover: isize,
+}
+
+#[derive(Debug,Eq,PartialEq,Clone)]
+pub struct ProductionPlan {
+    pub target: String,
+    pub amount: isize,
+    /// Every chemical that is needed, each one before its ingredients.
+    pub steps: Vec<Production>,
+}
+
+impl ProductionPlan {
+    pub fn get(&self, chemical: &str) -> Option<&Production> {
+        self.steps.iter().find(|p| p.chemical == chemical)
     }
-    fn need_to_continue(needs: &FxHashMap<Rc<String>, isize>) -> bool {
-        needs.iter().any(|n| &n.0[..] != "ORE" && *n.1 > 0)
+
+    /// Amount of ORE the plan consumes.
+    pub fn ore(&self) -> isize {
+        self.get("ORE").map_or(0, |p| p.needed)
     }
+}
 
-    buf.clear();
-    while need_to_continue(&*needs) {
-        //eprintln!("needs: {:?}", needs);
-        for (elem, amount_needed) in &*needs {
-            if &elem[..] == "ORE" || *amount_needed <= 0 {
-                continue;
+/// Renders the plan as a table with one row per chemical.
+impl fmt::Display for ProductionPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let header = ["chemical", "runs", "produced", "needed", "leftover"];
+        let rows: Vec<[String; 5]> = self.steps.iter().map(|p| match p.runs {
+            Some(runs) => [p.chemical.clone(), runs.to_string(), p.produced.to_string(), p.needed.to_string(), p.leftover.to_string()],
+            None => [p.chemical.clone(), "-".to_owned(), "-".to_owned(), p.needed.to_string(), "-".to_owned()],
+        }).collect();
+        let mut widths = header.map(|h| h.len());
+        for row in &rows {
+            for (width, cell) in widths.iter_mut().zip(row.iter()) {
+                *width = (*width).max(cell.len());
             }
-            let recipe = *recipies.get(elem).expect("missing recipe");
-            let num = num_applications(*amount_needed, recipe.output.amount);
-            //eprintln!("fabbing {} units of {}", num*recipe.output.amount, recipe.output.element);
-            buf.extend(recipe.input.iter().map(|i| i.times(num)));
-            buf.push(recipe.output.times(-num));
         }
-        for change in &*buf {
-            let required_amount = needs.entry(change.element.clone()).or_insert(0);
-            *required_amount += change.amount;
+        let header = header.map(|h| h.to_owned());
+        for row in std::iter::once(&header).chain(rows.iter()) {
+            write!(f, "{:<w$}", row[0], w = widths[0])?;
+            for (cell, width) in row.iter().zip(widths.iter()).skip(1) {
+                write!(f, "  {:>w$}", cell, w = width)?;
+            }
+            writeln!(f)?;
         }
-        buf.clear();
+        Ok(())
     }
 }
 
+#[aoc(day14, part1)]
+pub fn part1(input: &Reactions) -> isize {
+    input.ore_for_fuel(1)
+}
+
+const ORE_AVAILABLE: isize = 1_000_000_000_000;
+
+#[aoc(day14, part2)]
+pub fn part2(input: &Reactions) -> isize {
+    input.max_fuel(ORE_AVAILABLE)
+}
+
+/// Writes the reactions as a Graphviz digraph with an edge from every ingredient to the chemical it
+/// is used for, labelled with the amounts consumed and produced by a single reaction.
+pub fn write_dot<W: Write>(out: &mut W, recipes: &[Recipe]) -> io::Result<()> {
+    writeln!(out, "digraph reactions {{")?;
+    writeln!(out, "    {} [shape=box];", quote("ORE"))?;
+    writeln!(out, "    {} [shape=doublecircle];", quote("FUEL"))?;
+    for recipe in recipes {
+        for ingredient in &recipe.input {
+            let label = format!("{} → {}", ingredient.amount, recipe.output.amount);
+            writeln!(out, "    {} -> {} [label={}];", quote(&ingredient.element), quote(&recipe.output.element), quote(&label))?;
+        }
+    }
+    writeln!(out, "}}")
+}
+
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn generator_ok(input: &str) -> Reactions {
+        generator(input).unwrap_or_else(|e| panic!("invalid reactions: {}", e))
+    }
+
     fn ingredient(amount: isize, element: &str) -> Ingredient {
         Ingredient {
             amount,
@@ -143,7 +428,7 @@ mod tests {
 
     #[test]
     fn mini_recipe() {
-        assert_eq!(generator("1 A=>2 B"), vec![Recipe {
+        assert_eq!(parse_recipes("1 A=>2 B").unwrap(), vec![Recipe {
             input: vec![ingredient(1, "A")],
             output: ingredient(2, "B")
         }]);
@@ -151,7 +436,7 @@ mod tests {
 
     #[test]
     fn space_recipe() {
-        assert_eq!(generator("      1 A    =>    2    B   "), vec![Recipe {
+        assert_eq!(parse_recipes("      1 A    =>    2    B   ").unwrap(), vec![Recipe {
             input: vec![ingredient(1, "A")],
             output: ingredient(2, "B")
         }]);
@@ -159,7 +444,7 @@ mod tests {
 
     #[test]
     fn large_recipe() {
-        assert_eq!(generator("2 LCHZ, 13 JTJT, 10 TPXCK => 3 RSZF"), vec![Recipe{
+        assert_eq!(parse_recipes("2 LCHZ, 13 JTJT, 10 TPXCK => 3 RSZF").unwrap(), vec![Recipe{
             input: vec![ingredient(2, "LCHZ"), ingredient(13, "JTJT"), ingredient(10, "TPXCK")],
             output: ingredient(3, "RSZF")
         }]);
@@ -167,7 +452,7 @@ mod tests {
 
     #[test]
     fn multiple_recipes() {
-        assert_eq!(generator("1 A=>2 B\n2 C => 4 D"), vec![
+        assert_eq!(parse_recipes("1 A=>2 B\n2 C => 4 D").unwrap(), vec![
             Recipe {
                 input: vec![ingredient(1, "A")],
                 output: ingredient(2, "B")
@@ -179,9 +464,152 @@ mod tests {
         ]);
     }
 
+    #[test]
+    fn unparseable_lines() {
+        assert_eq!(generator("10 ORE => 10 A\n\n7 A, 1 ORE 1 FUEL").unwrap_err(), ReactionError::Unparseable {
+            line: 3, content: "7 A, 1 ORE 1 FUEL".to_owned(), reason: "missing '=>'".to_owned()
+        });
+        assert_eq!(generator("10 ORE => 10 A\n7 A, ORE => 1 FUEL").unwrap_err(), ReactionError::Unparseable {
+            line: 2, content: "7 A, ORE => 1 FUEL".to_owned(), reason: "expected AMOUNT CHEMICAL, found 'ORE'".to_owned()
+        });
+        match generator("0 ORE => 1 FUEL").unwrap_err() {
+            ReactionError::Unparseable { line: 1, reason, .. } => assert_eq!(reason, "amount of ORE must be positive"),
+            other => panic!("unexpected error {:?}", other),
+        }
+    }
+
+    #[test]
+    fn invalid_reactions() {
+        assert_eq!(generator("1 ORE => 1 A\n1 A, 2 B, 3 C => 1 FUEL").unwrap_err(),
+                   ReactionError::MissingRecipe(vec!["B".to_owned(), "C".to_owned()]));
+        assert_eq!(generator("1 ORE => 1 A").unwrap_err(), ReactionError::MissingRecipe(vec!["FUEL".to_owned()]));
+        assert_eq!(generator("1 ORE => 1 A\n2 ORE => 1 A\n1 A => 1 FUEL").unwrap_err(),
+                   ReactionError::DuplicateProducer(vec!["A".to_owned()]));
+        assert_eq!(generator("1 ORE, 1 C => 1 A\n1 A => 1 B\n1 B => 1 C\n1 B => 1 FUEL").unwrap_err(),
+                   ReactionError::Cycle(vec!["B".to_owned(), "A".to_owned(), "C".to_owned()]));
+        assert_eq!(generator("1 ORE => 1 A\n1 ORE => 1 X\n1 X => 1 Y\n1 A => 1 FUEL").unwrap_err(),
+                   ReactionError::Unreachable(vec!["X".to_owned(), "Y".to_owned()]));
+        assert_eq!(ReactionError::Cycle(vec!["B".to_owned(), "A".to_owned()]).to_string(), "reactions form a cycle: B => A => B");
+    }
+
+    #[test]
+    fn production_plan_example1() {
+        let reactions = generator_ok("10 ORE => 10 A
+1 ORE => 1 B
+7 A, 1 B => 1 C
+7 A, 1 C => 1 D
+7 A, 1 D => 1 E
+7 A, 1 E => 1 FUEL");
+        let plan = reactions.plan("FUEL", 1).unwrap();
+        assert_eq!(plan.ore(), 31);
+        assert_eq!(plan.get("A"), Some(&Production { chemical: "A".to_owned(), runs: Some(3), produced: 30, needed: 28, leftover: 2 }));
+        assert_eq!(plan.to_string(), "chemical  runs  produced  needed  leftover
+FUEL         1         1       1         0
+E            1         1       1         0
+D            1         1       1         0
+C            1         1       1         0
+B            1         1       1         0
+A            3        30      28         2
+ORE          -         -      31         -
+");
+        // intermediate targets
+        let plan = reactions.plan("C", 2).unwrap();
+        assert_eq!(plan.steps.iter().map(|p| &p.chemical[..]).collect::<Vec<_>>(), vec!["C", "B", "A", "ORE"]);
+        assert_eq!(plan.ore(), 2 * 10 + 2);
+        assert_eq!(reactions.plan("ORE", 5).unwrap().ore(), 5);
+        assert_eq!(reactions.plan("X", 1), Err(ReactionError::UnknownChemical("X".to_owned())));
+        // the ORE needed fits, but the FUEL produced by the last run doesn't
+        let batches = generator_ok("1 ORE => 10 FUEL");
+        assert_eq!(batches.plan("FUEL", isize::MAX), Err(ReactionError::Overflow("FUEL".to_owned())));
+        assert_eq!(batches.plan("FUEL", isize::MAX / 10 * 10).unwrap().ore(), isize::MAX / 10);
+    }
+
+    #[test]
+    fn production_plan_matches_part1() {
+        let reactions = generator_ok("9 ORE => 2 A
+8 ORE => 3 B
+7 ORE => 5 C
+3 A, 4 B => 1 AB
+5 B, 7 C => 1 BC
+4 C, 1 A => 1 CA
+2 AB, 3 BC, 4 CA => 1 FUEL");
+        let plan = reactions.plan("FUEL", 1).unwrap();
+        assert_eq!(plan.ore(), 165);
+        for production in plan.steps.iter().filter(|p| p.runs.is_some()) {
+            assert!(production.leftover >= 0, "{:?}", production);
+        }
+        // A: 2 AB need 6, 4 CA need 4 => 10 => 5 runs, no waste
+        assert_eq!(plan.get("A").unwrap().runs, Some(5));
+        assert_eq!(plan.get("A").unwrap().leftover, 0);
+    }
+
+    fn inventory(items: &[(&str, isize)]) -> Inventory {
+        items.iter().map(|&(c, amount)| (c.to_owned(), amount)).collect()
+    }
+
+    fn water_reactions() -> Reactions {
+        Reactions::with_raw_materials(parse_recipes("2 ORE, 1 WATER => 3 A\n3 A, 2 WATER => 1 FUEL").unwrap(), &["ORE", "WATER"], "FUEL")
+            .unwrap_or_else(|e| panic!("invalid reactions: {}", e))
+    }
+
+    #[test]
+    fn custom_raw_materials_and_target() {
+        let recipes = parse_recipes("1 ORE => 1 A\n1 A, 1 WATER => 1 FUEL").unwrap();
+        assert_eq!(Reactions::new(recipes.clone()).unwrap_err(), ReactionError::MissingRecipe(vec!["WATER".to_owned()]));
+        assert!(Reactions::with_raw_materials(recipes.clone(), &["ORE", "WATER"], "FUEL").is_ok());
+        assert_eq!(Reactions::with_raw_materials(recipes.clone(), &["ORE", "WATER"], "A").unwrap_err(),
+                   ReactionError::Unreachable(vec!["FUEL".to_owned()]));
+        // a raw material is never produced, even if there is a recipe for it
+        let reactions = Reactions::with_raw_materials(recipes, &["A", "WATER"], "FUEL").unwrap();
+        assert_eq!(reactions.raw_materials_needed("FUEL", 2, &Inventory::new()).unwrap(), inventory(&[("A", 2), ("WATER", 2)]));
+    }
+
+    #[test]
+    fn raw_materials_needed() {
+        let reactions = water_reactions();
+        let empty = Inventory::new();
+        assert_eq!(reactions.raw_materials_needed("FUEL", 1, &empty).unwrap(), inventory(&[("ORE", 2), ("WATER", 3)]));
+        assert_eq!(reactions.raw_materials_needed("FUEL", 2, &empty).unwrap(), inventory(&[("ORE", 4), ("WATER", 6)]));
+        // 4 A in stock: only 2 more A needed, made by one run
+        assert_eq!(reactions.raw_materials_needed("FUEL", 2, &inventory(&[("A", 4)])).unwrap(), inventory(&[("ORE", 2), ("WATER", 5)]));
+        assert_eq!(reactions.raw_materials_needed("FUEL", 1, &inventory(&[("ORE", 10), ("WATER", 1)])).unwrap(), inventory(&[("WATER", 2)]));
+        assert_eq!(reactions.raw_materials_needed("FUEL", 1, &inventory(&[("FUEL", 1)])).unwrap(), empty);
+        assert_eq!(reactions.raw_materials_needed("A", 4, &empty).unwrap(), inventory(&[("ORE", 4), ("WATER", 2)]));
+        assert_eq!(reactions.raw_materials_needed("B", 1, &empty), Err(ReactionError::UnknownChemical("B".to_owned())));
+        assert_eq!(reactions.raw_materials_needed("FUEL", isize::MAX / 2, &empty), Err(ReactionError::Overflow("A".to_owned())));
+    }
+
+    #[test]
+    fn max_producible() {
+        let reactions = water_reactions();
+        // n FUEL need 2n ORE and 3n WATER
+        assert_eq!(reactions.max_producible("FUEL", &inventory(&[("ORE", 10), ("WATER", 20)])), Ok(5));
+        assert_eq!(reactions.max_producible("FUEL", &inventory(&[("ORE", 10), ("WATER", 20), ("A", 3)])), Ok(6));
+        assert_eq!(reactions.max_producible("FUEL", &inventory(&[("ORE", 10)])), Ok(0));
+        assert_eq!(reactions.max_producible("A", &inventory(&[("ORE", 7), ("WATER", 5)])), Ok(9));
+        assert_eq!(reactions.max_producible("WATER", &inventory(&[("WATER", 4)])), Ok(4));
+        assert_eq!(reactions.max_producible("WATER", &inventory(&[("WATER", isize::MAX)])), Ok(isize::MAX));
+        // anything that needs more than isize::MAX is out of reach
+        assert_eq!(reactions.max_producible("FUEL", &inventory(&[("ORE", isize::MAX), ("WATER", isize::MAX)])), Ok(isize::MAX / 3));
+    }
+
+    #[test]
+    fn reactions_dot() {
+        let mut out = Vec::new();
+        write_dot(&mut out, generator_ok("10 ORE => 10 A\n7 A, 1 ORE => 1 FUEL").recipes()).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), r#"digraph reactions {
+    "ORE" [shape=box];
+    "FUEL" [shape=doublecircle];
+    "ORE" -> "A" [label="10 → 10"];
+    "A" -> "FUEL" [label="7 → 1"];
+    "ORE" -> "FUEL" [label="1 → 1"];
+}
+"#);
+    }
+
     #[test]
     fn part1_example1() {
-        assert_eq!(part1(&generator("10 ORE => 10 A
+        assert_eq!(part1(&generator_ok("10 ORE => 10 A
 1 ORE => 1 B
 7 A, 1 B => 1 C
 7 A, 1 C => 1 D
@@ -191,7 +619,7 @@ mod tests {
 
     #[test]
     fn part1_example2() {
-        assert_eq!(part1(&generator("9 ORE => 2 A
+        assert_eq!(part1(&generator_ok("9 ORE => 2 A
 8 ORE => 3 B
 7 ORE => 5 C
 3 A, 4 B => 1 AB
@@ -202,7 +630,7 @@ mod tests {
 
     #[test]
     fn part1_example3() {
-        assert_eq!(part1(&generator("157 ORE => 5 NZVS
+        assert_eq!(part1(&generator_ok("157 ORE => 5 NZVS
 165 ORE => 6 DCFZ
 44 XJWVT, 5 KHKGT, 1 QDVJ, 29 NZVS, 9 GPVTF, 48 HKGWZ => 1 FUEL
 12 HKGWZ, 1 GPVTF, 8 PSHF => 9 QDVJ
@@ -215,7 +643,7 @@ mod tests {
 
     #[test]
     fn part1_example4() {
-        assert_eq!(part1(&generator("2 VPVL, 7 FWMGM, 2 CXFTF, 11 MNCFX => 1 STKFG
+        assert_eq!(part1(&generator_ok("2 VPVL, 7 FWMGM, 2 CXFTF, 11 MNCFX => 1 STKFG
 17 NVRVD, 3 JNWZP => 8 VPVL
 53 STKFG, 6 MNCFX, 46 VJHF, 81 HVMC, 68 CXFTF, 25 GNMV => 1 FUEL
 22 VJHF, 37 MNCFX => 5 FWMGM
@@ -231,7 +659,7 @@ mod tests {
 
     #[test]
     fn part1_example5() {
-        assert_eq!(part1(&generator("171 ORE => 8 CNZTR
+        assert_eq!(part1(&generator_ok("171 ORE => 8 CNZTR
 7 ZLQW, 3 BMBT, 9 XCVML, 26 XMNCP, 1 WPTQ, 2 MZWV, 1 RJRHP => 4 PLWSL
 114 ORE => 4 BHXH
 14 VRPVC => 6 BMBT
@@ -250,11 +678,9 @@ mod tests {
 5 BHXH, 4 VRPVC => 5 LTCX")), 2210736);
     }
 
-    // the part2 tests are mega-slow (take minutes to complete)
-
     #[test]
     fn part2_example3() {
-        assert_eq!(part2(&generator("157 ORE => 5 NZVS
+        assert_eq!(part2(&generator_ok("157 ORE => 5 NZVS
 165 ORE => 6 DCFZ
 44 XJWVT, 5 KHKGT, 1 QDVJ, 29 NZVS, 9 GPVTF, 48 HKGWZ => 1 FUEL
 12 HKGWZ, 1 GPVTF, 8 PSHF => 9 QDVJ
@@ -269,7 +695,7 @@ mod tests {
 
     #[test]
     fn part2_example4() {
-        assert_eq!(part2(&generator("2 VPVL, 7 FWMGM, 2 CXFTF, 11 MNCFX => 1 STKFG
+        assert_eq!(part2(&generator_ok("2 VPVL, 7 FWMGM, 2 CXFTF, 11 MNCFX => 1 STKFG
 17 NVRVD, 3 JNWZP => 8 VPVL
 53 STKFG, 6 MNCFX, 46 VJHF, 81 HVMC, 68 CXFTF, 25 GNMV => 1 FUEL
 22 VJHF, 37 MNCFX => 5 FWMGM
@@ -285,7 +711,7 @@ mod tests {
 
     #[test]
     fn part2_example5() {
-        assert_eq!(part2(&generator("171 ORE => 8 CNZTR
+        assert_eq!(part2(&generator_ok("171 ORE => 8 CNZTR
 7 ZLQW, 3 BMBT, 9 XCVML, 26 XMNCP, 1 WPTQ, 2 MZWV, 1 RJRHP => 4 PLWSL
 114 ORE => 4 BHXH
 14 VRPVC => 6 BMBT
@@ -3,6 +3,11 @@ use async_std::task;
 use fxhash::FxHashMap;
 use std::collections::VecDeque;
 use std::mem::swap;
+use std::fmt::{self, Display, Formatter, Write as FmtWrite};
+use std::io::{self, Write};
+use std::iter::FromIterator;
+use std::str::FromStr;
+use itertools::Itertools;
 
 #[aoc_generator(day15)]
 pub fn generator(input: &str) -> Vec<isize> {
@@ -27,8 +32,7 @@ pub fn part1(input: &Vec<isize>) -> isize {
             Tile::Unknown(initial, command, distance) => {
                 let (status, state) = step(&initial, command);
                 (status, state, distance)
-            },
-            Tile::Pressurized(_) => panic!("already pressurized")
+            }
         };
         tile = Tile::Known(status);
         swap(tile_entry, &mut tile);
@@ -53,43 +57,45 @@ pub fn part1(input: &Vec<isize>) -> isize {
     panic!("Did not find oxygen system!");
 }
 
+#[aoc(day15, part1, replay)]
+pub fn part1_replay(input: &Vec<isize>) -> usize {
+    let area = explore(input).area;
+    let path = area.shortest_path((0, 0), area.oxygen.expect("to find oxygen")).expect("oxygen to be reachable");
+
+    // Double-check the path against a fresh droid
+    let statuses = replay(&mut Computer::new(input.clone()), &path);
+    assert_eq!(statuses.last(), Some(&Status::Oxygen), "replayed path should end at the oxygen system");
+    path.len()
+}
+
 #[aoc(day15, part2)]
-pub fn part2(input: &Vec<isize>) -> isize {
-    let mut m: FxHashMap<(isize, isize), Tile> = FxHashMap::default();
-    m.insert((0, 0), Tile::Starting(Computer::new(input.clone())));
+pub fn part2(input: &Vec<isize>) -> usize {
+    pressurization_time(&explore(input).area)
+}
 
-    // Explore the space (find oxygen as a side effect)
-    let oxygen_coord = explore(&mut m);
+#[aoc(day15, part2, physical)]
+pub fn part2_physical(input: &Vec<isize>) -> usize {
+    pressurization_time(&explore_physical(input).area)
+}
 
-    // Flood the space from the oxygen station
-    *m.get_mut(&oxygen_coord).expect("oxygen tile to exist") = Tile::Pressurized(0);
-    let mut vacuum = VecDeque::from(vec![oxygen_coord]);
-    while let Some(coord) = vacuum.pop_front() {
-        let tile_entry = m.get(&coord).expect("map should be known");
+fn pressurization_time(area: &AreaMap) -> usize {
+    let oxygen_coord = area.oxygen.expect("to find oxygen");
 
-        if let Tile::Pressurized(time) = *tile_entry {
-            for cmd in ALL_DIRECTIONS.iter() {
-                let next_coord = cmd.direction(coord);
-                let next_tile = m.get_mut(&next_coord).expect("neighbours of pressurized tiles to be known");
-                if let Tile::Known(Status::Empty) = *next_tile {
-                    *next_tile =  Tile::Pressurized(time + 1);
-                    vacuum.push_back(next_coord);
-                }
-            }
-        } else {
-            panic!("expect pressurized tile in vacuum list")
-        }
-    }
+    // Flood the space from the oxygen station, the last tile to be pressurized determines the time
+    area.distances_from(oxygen_coord).values().max().copied().expect("maximum pressurization time")
+}
 
-    // find maximum pressurization time
-    m.values().flat_map(|t| match t {
-        Tile::Pressurized(time) => Some(*time),
-        _ => None
-    }).max().expect("maximum pressurization time")
+/// Result of exploring the area, including the number of movement commands it took.
+pub struct Exploration {
+    pub area: AreaMap,
+    pub commands: usize,
 }
 
-fn explore(m: &mut FxHashMap<(isize, isize), Tile>) -> (isize, isize) {
-    let mut oxygen_coord = None;
+/// Explores the entire area by cloning the droid for every frontier tile (BFS).
+pub fn explore(input: &[isize]) -> Exploration {
+    let mut commands = 0usize;
+    let mut m: FxHashMap<Vector2, Tile> = FxHashMap::default();
+    m.insert((0, 0), Tile::Starting(Computer::new(Vec::from(input))));
     let mut unknowns = VecDeque::from(vec![(0, 0)]);
     while let Some(coord) = unknowns.pop_front() {
         let tile_entry = m.get_mut(&coord).expect("unknowns should already exist");
@@ -101,19 +107,18 @@ fn explore(m: &mut FxHashMap<(isize, isize), Tile>) -> (isize, isize) {
             Tile::Known(_) => panic!("unknowns should not contain known tiles"),
             Tile::Starting(c) => (Status::Empty, c, 0),
             Tile::Unknown(initial, command, distance) => {
+                commands += 1;
                 let (status, state) = step(&initial, command);
                 (status, state, distance)
-            },
-            Tile::Pressurized(_) => panic!("Oxygen not found yet, cannot be pressurized")
+            }
         };
         tile = Tile::Known(status);
         swap(tile_entry, &mut tile);
 
         // Act on current tile
         match status {
-            Status::Oxygen => oxygen_coord = Some(coord),
             Status::Wall => (), // nothing to do
-            Status::Empty => {
+            Status::Empty | Status::Oxygen => {
                 for cmd in ALL_DIRECTIONS.iter() {
                     // Go in all directions, and add 'unknowns' if we haven't seen that tile yet
                     let next_coord = cmd.direction(coord);
@@ -126,19 +131,310 @@ fn explore(m: &mut FxHashMap<(isize, isize), Tile>) -> (isize, isize) {
         }
     }
 
-    oxygen_coord.expect("to find oxygen")
+    let area = m.into_iter().map(|(coord, tile)| match tile {
+        Tile::Known(status) => (coord, status),
+        _ => panic!("all tiles should be known after exploration")
+    }).collect();
+    Exploration { area, commands }
+}
+
+/// Explores the entire area with a single droid that physically moves around (DFS with backtracking).
+/// Only talks to the droid through the `Hal`, so it does not rely on being able to clone the computer.
+pub fn explore_physical(input: &[isize]) -> Exploration {
+    let mut explorer = DepthFirstExplorer::new();
+    task::block_on(Computer::new(input.to_vec()).execute(&mut explorer));
+    Exploration { area: explorer.known.iter().map(|(c, s)| (*c, *s)).collect(), commands: explorer.commands }
+}
+
+/// Depth-first exploration state. The droid only ever moves into unknown tiles or back
+/// along the path it came from, so the path stack is all we need to backtrack.
+struct DepthFirstExplorer {
+    known: FxHashMap<Vector2, Status>,
+    position: Vector2,
+    path: Vec<Command>,
+    next: Option<(Command, bool)>,
+    commands: usize,
+}
+
+impl DepthFirstExplorer {
+    fn new() -> DepthFirstExplorer {
+        let mut known = FxHashMap::default();
+        known.insert((0, 0), Status::Empty);
+        let mut explorer = DepthFirstExplorer { known, position: (0, 0), path: Vec::new(), next: None, commands: 0 };
+        explorer.next = explorer.plan();
+        explorer
+    }
+
+    /// Picks the next command: an unknown neighbour if there is one, otherwise backtrack.
+    /// The flag indicates whether the command is a backtracking move.
+    fn plan(&self) -> Option<(Command, bool)> {
+        ALL_DIRECTIONS.iter()
+            .find(|cmd| !self.known.contains_key(&cmd.direction(self.position)))
+            .map(|cmd| (*cmd, false))
+            .or_else(|| self.path.last().map(|cmd| (cmd.opposite(), true)))
+    }
+
+    fn command(&mut self) -> Command {
+        self.commands += 1;
+        self.next.expect("explorer should be powered down when done").0
+    }
+
+    fn report(&mut self, status: Status) {
+        let (cmd, backtracking) = self.next.expect("response to a command");
+        let target = cmd.direction(self.position);
+        if backtracking {
+            assert_ne!(status, Status::Wall, "droid should be able to retrace its steps");
+            self.path.pop();
+            self.position = target;
+        } else {
+            self.known.insert(target, status);
+            if status != Status::Wall {
+                self.path.push(cmd);
+                self.position = target;
+            }
+        }
+        self.next = self.plan();
+    }
+}
+
+#[async_trait]
+impl InputDevice for DepthFirstExplorer {
+    async fn input(&mut self) -> isize {
+        self.command() as isize
+    }
+}
+
+#[async_trait]
+impl OutputDevice for DepthFirstExplorer {
+    async fn output(&mut self, value: isize) -> () {
+        self.report(Status::from(value))
+    }
+}
+
+impl Hal for DepthFirstExplorer {
+    fn powered(&mut self) -> bool { self.next.is_some() }
+}
+
+type Vector2 = (isize, isize);
+
+/// A fully explored area. Tiles outside of the bounding box or never visited are `None`.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct AreaMap {
+    tiles: Vec<Option<Status>>,
+    min: Vector2,
+    width: usize,
+    height: usize,
+    pub oxygen: Option<Vector2>,
+}
+
+impl AreaMap {
+    pub fn get(&self, coord: Vector2) -> Option<Status> {
+        let (x, y) = (coord.0 - self.min.0, coord.1 - self.min.1);
+        if x < 0 || y < 0 || x as usize >= self.width || y as usize >= self.height {
+            None
+        } else {
+            self.tiles[y as usize * self.width + x as usize]
+        }
+    }
+
+    fn passable(&self, coord: Vector2) -> bool {
+        match self.get(coord) {
+            Some(Status::Empty) | Some(Status::Oxygen) => true,
+            Some(Status::Wall) | None => false
+        }
+    }
+
+    fn coords(&self) -> impl Iterator<Item=Vector2> + '_ {
+        (0..self.height as isize).flat_map(move |y| (0..self.width as isize).map(move |x| (self.min.0 + x, self.min.1 + y)))
+    }
+
+    /// BFS over all passable tiles, remembering the command that was used to enter each tile.
+    fn flood(&self, from: Vector2) -> FxHashMap<Vector2, (usize, Option<Command>)> {
+        let mut visited = FxHashMap::default();
+        if !self.passable(from) {
+            return visited;
+        }
+        visited.insert(from, (0, None));
+        let mut pending = VecDeque::from(vec![(from, 0usize)]);
+        while let Some((coord, distance)) = pending.pop_front() {
+            for cmd in ALL_DIRECTIONS.iter() {
+                let next_coord = cmd.direction(coord);
+                if self.passable(next_coord) && !visited.contains_key(&next_coord) {
+                    visited.insert(next_coord, (distance + 1, Some(*cmd)));
+                    pending.push_back((next_coord, distance + 1));
+                }
+            }
+        }
+        visited
+    }
+
+    /// Number of steps needed to reach every reachable tile from `from`.
+    pub fn distances_from(&self, from: Vector2) -> FxHashMap<Vector2, usize> {
+        self.flood(from).into_iter().map(|(coord, (distance, _))| (coord, distance)).collect()
+    }
+
+    /// Sequence of movement commands on the shortest path from `from` to `to`.
+    pub fn shortest_path(&self, from: Vector2, to: Vector2) -> Option<Vec<Command>> {
+        let visited = self.flood(from);
+        visited.get(&to)?;
+        let mut path = Vec::new();
+        let mut coord = to;
+        while let Some((_, Some(cmd))) = visited.get(&coord) {
+            path.push(*cmd);
+            coord = cmd.opposite().direction(coord);
+        }
+        path.reverse();
+        Some(path)
+    }
+
+    /// Writes the map as a binary PPM (P6) image, with `scale` pixels per tile.
+    pub fn write_ppm(&self, out: &mut impl Write, scale: usize) -> io::Result<()> {
+        write!(out, "P6\n{} {}\n255\n", self.width * scale, self.height * scale)?;
+        let coords: Vec<Vector2> = self.coords().collect();
+        for row in coords.chunks(self.width.max(1)) {
+            let mut line = Vec::with_capacity(row.len() * scale * 3);
+            for coord in row {
+                let color: [u8; 3] = match (*coord == (0, 0), self.get(*coord)) {
+                    (true, _) => [0x20, 0xc0, 0x20],
+                    (_, None) => [0x00, 0x00, 0x00],
+                    (_, Some(Status::Wall)) => [0x60, 0x60, 0x60],
+                    (_, Some(Status::Empty)) => [0xff, 0xff, 0xff],
+                    (_, Some(Status::Oxygen)) => [0x20, 0x40, 0xff],
+                };
+                for _ in 0..scale {
+                    line.extend_from_slice(&color);
+                }
+            }
+            for _ in 0..scale {
+                out.write_all(&line)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromIterator<(Vector2, Status)> for AreaMap {
+    fn from_iter<T: IntoIterator<Item=(Vector2, Status)>>(iter: T) -> Self {
+        let known: Vec<(Vector2, Status)> = iter.into_iter().collect();
+        let (min_x, max_x) = known.iter().map(|t| (t.0).0).minmax().into_option().unwrap_or((0, 0));
+        let (min_y, max_y) = known.iter().map(|t| (t.0).1).minmax().into_option().unwrap_or((0, 0));
+        let width = (max_x - min_x + 1) as usize;
+        let height = (max_y - min_y + 1) as usize;
+        let mut area = AreaMap { tiles: vec![None; width * height], min: (min_x, min_y), width, height, oxygen: None };
+        for (coord, status) in known {
+            let idx = (coord.1 - min_y) as usize * width + (coord.0 - min_x) as usize;
+            area.tiles[idx] = Some(status);
+            if status == Status::Oxygen {
+                area.oxygen = Some(coord);
+            }
+        }
+        area
+    }
+}
+
+/// A character in the ASCII rendering of an `AreaMap` that doesn't stand for a tile.
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub struct MapError {
+    /// Starting at 1
+    pub line: usize,
+    /// Starting at 1
+    pub column: usize,
+    pub found: char,
+}
+
+impl Display for MapError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}, column {}: unexpected character '{}' in map", self.line, self.column, self.found)
+    }
+}
+
+impl std::error::Error for MapError {}
+
+/// Parses the ASCII rendering. `D` marks the droid's starting position, which becomes the origin.
+impl FromStr for AreaMap {
+    type Err = MapError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let origin = input.lines().enumerate()
+            .flat_map(|(y, line)| line.chars().position(|c| c == 'D').map(|x| (x as isize, y as isize)))
+            .next().unwrap_or((0, 0));
+        input.lines().enumerate().flat_map(|(y, line)| {
+            line.chars().enumerate().filter_map(move |(x, c)| {
+                let coord = (x as isize - origin.0, y as isize - origin.1);
+                match c {
+                    '#' => Some(Ok((coord, Status::Wall))),
+                    '.' | 'D' => Some(Ok((coord, Status::Empty))),
+                    'O' => Some(Ok((coord, Status::Oxygen))),
+                    ' ' => None,
+                    found => Some(Err(MapError { line: y + 1, column: x + 1, found }))
+                }
+            })
+        }).collect()
+    }
+}
+
+impl Display for AreaMap {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for y in 0..self.height as isize {
+            for x in 0..self.width as isize {
+                let coord = (self.min.0 + x, self.min.1 + y);
+                f.write_char(match (coord == (0, 0), self.get(coord)) {
+                    (true, _) => 'D',
+                    (_, None) => ' ',
+                    (_, Some(Status::Wall)) => '#',
+                    (_, Some(Status::Empty)) => '.',
+                    (_, Some(Status::Oxygen)) => 'O',
+                })?;
+            }
+            f.write_char('\n')?;
+        }
+        Ok(())
+    }
+}
+
+/// Feeds a fixed sequence of commands to a droid and records its responses.
+/// Powers the computer down once the last response has been received.
+struct ReplayDevice {
+    commands: VecDeque<Command>,
+    statuses: Vec<Status>,
+    expected: usize,
+}
+
+#[async_trait]
+impl InputDevice for ReplayDevice {
+    async fn input(&mut self) -> isize {
+        self.commands.pop_front().expect("droid should not ask for more commands than replayed") as isize
+    }
+}
+
+#[async_trait]
+impl OutputDevice for ReplayDevice {
+    async fn output(&mut self, value: isize) -> () {
+        self.statuses.push(Status::from(value));
+    }
+}
+
+impl Hal for ReplayDevice {
+    fn powered(&mut self) -> bool { self.statuses.len() < self.expected }
+}
+
+/// Replays `commands` against a real droid, returning the status reported after each command.
+/// The computer is left in a state where it can accept further commands.
+pub(crate) fn replay(computer: &mut Computer, commands: &[Command]) -> Vec<Status> {
+    let mut hal = ReplayDevice { commands: commands.iter().copied().collect(), statuses: Vec::with_capacity(commands.len()), expected: commands.len() };
+    task::block_on(computer.execute(&mut hal));
+    hal.statuses
 }
 
 enum Tile {
     Starting(Computer),
     Unknown(Computer, Command, isize),
     Known(Status),
-    Pressurized(isize)
 }
 
 #[derive(Copy, Clone, Eq, PartialEq, Debug)]
 #[repr(isize)]
-enum Status {
+pub enum Status {
     Wall = 0isize,
     Empty = 1isize,
     Oxygen = 2isize,
@@ -157,7 +453,7 @@ impl From<isize> for Status {
 
 #[derive(Copy, Clone, Eq, PartialEq, Debug)]
 #[repr(isize)]
-enum Command {
+pub enum Command {
     North = 1isize,
     South = 2isize,
     West = 3isize,
@@ -175,6 +471,15 @@ impl Command {
             Command::East => (origin.0 + 1, origin.1)
         }
     }
+
+    fn opposite(self) -> Command {
+        match self {
+            Command::North => Command::South,
+            Command::South => Command::North,
+            Command::West => Command::East,
+            Command::East => Command::West
+        }
+    }
 }
 
 struct PowerDownOnOutput<I, O> {
@@ -229,8 +534,127 @@ fn step(initial: &Computer, command: Command) -> (Status, Computer) {
     (task::block_on(step_task), task::block_on(next_computer_task))
 }
 
-#[allow(unused)]
 #[cfg(test)]
 mod tests {
     use super::*;
+
+    // no line continuation: it would strip the leading space of the first row
+    const EXAMPLE: &str = " ##   
+#..## 
+#.#..#
+#.O.# 
+ ###  ";
+
+    #[test]
+    fn map_roundtrip() {
+        let area = "##\n#D\n".parse::<AreaMap>().expect("valid map");
+        assert_eq!(area.to_string(), "##\n#D\n");
+    }
+
+    #[test]
+    fn map_unexpected_character() {
+        let error = "##\n#D.\n#.x#".parse::<AreaMap>().unwrap_err();
+        assert_eq!(error, MapError { line: 3, column: 3, found: 'x' });
+        assert_eq!(error.to_string(), "line 3, column 3: unexpected character 'x' in map");
+    }
+
+    #[test]
+    fn flood_example() {
+        let area = EXAMPLE.parse::<AreaMap>().expect("valid map");
+        let oxygen = area.oxygen.expect("example has oxygen");
+        assert_eq!(area.distances_from(oxygen).values().max(), Some(&4));
+    }
+
+    #[test]
+    fn shortest_path_example() {
+        let area = EXAMPLE.parse::<AreaMap>().expect("valid map");
+        let oxygen = area.oxygen.expect("example has oxygen");
+        let path = area.shortest_path(oxygen, (4, 2)).expect("reachable");
+        assert_eq!(path, vec![Command::East, Command::North, Command::East]);
+        assert_eq!(area.get((1, 0)), Some(Status::Wall));
+        assert_eq!(area.shortest_path(oxygen, (1, 0)), None, "wall is unreachable");
+        assert_eq!(area.get((0, 0)), None);
+        assert_eq!(area.shortest_path(oxygen, (0, 0)), None, "unknown tile is unreachable");
+    }
+
+    #[test]
+    fn ppm_header() {
+        let area = EXAMPLE.parse::<AreaMap>().expect("valid map");
+        let mut buf = Vec::new();
+        area.write_ppm(&mut buf, 2).expect("write to vec");
+        assert!(buf.starts_with(b"P6\n12 10\n255\n"));
+        assert_eq!(buf.len(), "P6\n12 10\n255\n".len() + 12 * 10 * 3);
+    }
+
+    /// Answers droid commands by looking at a known map.
+    fn simulate(area: &AreaMap, explorer: &mut DepthFirstExplorer) {
+        let mut position = (0, 0);
+        while explorer.next.is_some() {
+            let target = explorer.command().direction(position);
+            let status = area.get(target).unwrap_or(Status::Wall);
+            if status != Status::Wall {
+                position = target;
+            }
+            explorer.report(status);
+        }
+    }
+
+    /// `EXAMPLE` with the droid's starting position.
+    const DROID_EXAMPLE: &str = " ##   
+#..## 
+#D#..#
+#.O.# 
+ ###  ";
+
+    /// Intcode droid in an east-west corridor of three tiles, starting at the west end, with the
+    /// oxygen system at the east end:
+    ///
+    /// ```text
+    /// loop:   in c; if c == west goto west; if c == east goto east
+    /// wall:   out 0; goto loop
+    /// west:   if p == 0 goto wall; p -= 1; goto report
+    /// east:   if p == 2 goto wall; p += 1
+    /// report: out (p == 2) + 1; goto loop
+    /// ```
+    const CORRIDOR_DROID: [isize; 62] = [
+        3, 60, 1008, 60, 3, 61, 1005, 61, 21, 1008, 60, 4, 61, 1005, 61, 35, 104, 0, 1106, 0, 0,
+        1008, 59, 0, 61, 1005, 61, 16, 1001, 59, -1, 59, 1106, 0, 46,
+        1008, 59, 2, 61, 1005, 61, 16, 1001, 59, 1, 59,
+        1008, 59, 2, 61, 1001, 61, 1, 61, 4, 61, 1106, 0, 0,
+        0, 0, 0];
+
+    #[test]
+    fn exploration_commands() {
+        let physical = explore_physical(&CORRIDOR_DROID);
+        let cloning = explore(&CORRIDOR_DROID);
+        assert_eq!(physical.area, " ### \n#D.O#\n ### ".parse::<AreaMap>().expect("valid map"));
+        assert_eq!(cloning.area, physical.area);
+        // the single droid enters and leaves both other tiles and bumps into all 8 walls,
+        // the cloned droids only ever take one step from a known tile
+        assert_eq!(physical.commands, 2 * 2 + 8);
+        assert_eq!(cloning.commands, 2 + 8);
+    }
+
+    #[test]
+    fn physical_exploration_example() {
+        let area = DROID_EXAMPLE.parse::<AreaMap>().expect("valid map");
+        let mut explorer = DepthFirstExplorer::new();
+        simulate(&area, &mut explorer);
+        let explored: AreaMap = explorer.known.iter().map(|(c, s)| (*c, *s)).collect();
+        assert_eq!(explored, area);
+        assert_eq!(explorer.position, (0, 0), "droid returns to start");
+        assert_eq!(pressurization_time(&explored), 4);
+
+        // every reachable tile is entered and left again, every wall is bumped into once
+        assert_eq!(explorer.commands, 2 * 7 + 13);
+    }
+
+    #[test]
+    fn replay_open_space() {
+        // read command, report "moved", repeat
+        let mut computer = Computer::new(vec![3, 100, 104, 1, 1105, 1, 0]);
+        let statuses = replay(&mut computer, &[Command::North, Command::East]);
+        assert_eq!(statuses, vec![Status::Empty, Status::Empty]);
+        assert_eq!(replay(&mut computer, &[Command::South]), vec![Status::Empty]);
+    }
 }
\ No newline at end of file
@@ -1,10 +1,12 @@
 use serde::export::Formatter;
-use std::collections::VecDeque;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, VecDeque};
 use std::iter::once;
 use std::time::{Instant, Duration};
-use std::sync::atomic::{AtomicUsize, AtomicIsize, Ordering};
-use std::sync::Arc;
-use threadpool_crossbeam_channel::ThreadPool;
+use std::sync::atomic::{AtomicBool, AtomicUsize, AtomicIsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use fxhash::FxHashMap;
 
 #[aoc_generator(day18)]
 pub fn generator(input: &str) -> Vec<u8> {
@@ -12,101 +14,313 @@ pub fn generator(input: &str) -> Vec<u8> {
 }
 
 
-#[aoc(day18, part1, serial)]
-pub fn part1_serial(input: &Vec<u8>) -> isize {
-    let mut pending_states = VecDeque::new();
-    pending_states.push_front(State { maze: Maze::new(input.clone()), total_distance: 0 });
-
-    let mut low_score = std::isize::MAX;
-    let mut last_status_update = Instant::now();
-    let mut num_states_added = 0usize;
-    let mut num_states_explored = 0usize;
-    let interval = 5;
-    while let Some(mut state) = if pending_states.len() > 100_000_000 { pending_states.pop_back() } else { pending_states.pop_front() } {
-        let num_states_before = pending_states.len();
-        let last_score = state.explore(low_score, &mut pending_states);
-        num_states_added += pending_states.len() - num_states_before;
-        num_states_explored += 1;
-        low_score = low_score.min(last_score);
-        if last_status_update.elapsed() > Duration::from_secs(interval) {
-            last_status_update = Instant::now();
-            eprintln!("Low score: {} Pending States: {} Explore {}/s Add {}/s", low_score, pending_states.len(), num_states_explored / interval as usize, num_states_added / interval as usize);
-            num_states_added = 0;
-            num_states_explored = 0;
+#[aoc(day18, part1)]
+pub fn part1(input: &Vec<u8>) -> usize {
+    shortest_route(input).expect("all keys to be reachable").steps
+}
+
+/// Mazes with a single entrance get split into four vaults first.
+#[aoc(day18, part2)]
+pub fn part2(input: &Vec<u8>) -> usize {
+    let maze = if input.iter().filter(|&&c| c == b'@').count() == 1 { split_entrance(input) } else { input.clone() };
+    shortest_route(&maze).expect("all keys to be reachable").steps
+}
+
+/// Tuning for the branch and bound `Search`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct SearchOptions {
+    pub threads: usize,
+    /// Upper bound (in bytes, estimated) for queued states and the table of states seen so far,
+    /// which get half of it each. Once the queues' half is used up, workers explore the states they
+    /// find depth first on their own until queued states are taken again. Once the table's half is
+    /// used up, new states are no longer remembered for deduplication.
+    pub memory_budget: usize,
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        SearchOptions {
+            threads: std::thread::available_parallelism().map_or(1, |n| n.get()),
+            memory_budget: 256 << 20,
         }
     }
+}
 
-    low_score
+/// Snapshot of a running (or finished) `Search`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Progress {
+    /// Fewest steps found so far.
+    pub best: Option<isize>,
+    /// States waiting in the shared queues.
+    pub queued: usize,
+    pub explored: usize,
+    /// States dropped because an equivalent state (same position and keys) was cheaper.
+    pub duplicates: usize,
+    /// Memory used by queued states and the table of seen states.
+    pub memory_used: usize,
 }
 
-fn schedule_state(pool: &ThreadPool, state: State, low_score: &Arc<AtomicIsize>, num_states_added: &Arc<AtomicUsize>, num_states_explored: &Arc<AtomicUsize>) {
-    let num_states_added = num_states_added.clone();
-    let num_states_explored = num_states_explored.clone();
-    let low_score = low_score.clone();
-    let shared_pool = pool.clone();
-    pool.execute(move || {
-        let mut pending_states = VecDeque::new();
-        pending_states.push_front(state);
-
-        while let Some(mut state) = pending_states.pop_back() {
-            let mut last_score = low_score.load(Ordering::Relaxed);
-            let mut new_score = last_score.min(state.explore(last_score, &mut pending_states));
-            while let Err(updated_score) = low_score.compare_exchange(last_score, new_score, Ordering::SeqCst, Ordering::SeqCst) {
-                new_score = updated_score.min(new_score);
-                last_score = updated_score;
-                if new_score == last_score {
-                    break;
-                }
-            }
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Outcome {
+    /// The fewest steps needed, `None` if the keys can't be collected.
+    Finished(Option<isize>),
+    /// Cancelled before the search space was exhausted.
+    Cancelled(Progress),
+}
+
+/// Estimated size of an entry in the table of seen states.
+const SEEN_ENTRY_BYTES: usize = 2 * std::mem::size_of::<((usize, u32), isize)>();
+
+/// Part of the memory budget, in estimated bytes.
+struct Budget {
+    used: AtomicUsize,
+    limit: usize,
+}
+
+impl Budget {
+    fn new(limit: usize) -> Budget {
+        Budget { used: AtomicUsize::new(0), limit }
+    }
 
-            num_states_added.fetch_add(pending_states.len(), Ordering::Relaxed);
-            num_states_explored.fetch_add(1, Ordering::Relaxed);
+    /// Reserves `bytes`, if there is enough left.
+    fn reserve(&self, bytes: usize) -> bool {
+        self.used.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |used| {
+            Some(used + bytes).filter(|&total| total <= self.limit)
+        }).is_ok()
+    }
+
+    fn release(&self, bytes: usize) {
+        self.used.fetch_sub(bytes, Ordering::Relaxed);
+    }
+
+    fn used(&self) -> usize {
+        self.used.load(Ordering::Relaxed)
+    }
+}
+
+/// A state in one of the shared queues.
+struct Queued {
+    state: State,
+    /// Whether the state counts against the queues' budget (the initial state doesn't).
+    reserved: bool,
+}
+
+/// Everything the workers share. Every worker owns one queue and takes the newest state from it
+/// (depth first), idle workers steal the oldest states from the others.
+struct Shared {
+    queues: Vec<Mutex<VecDeque<Queued>>>,
+    /// Fewest steps seen so far for a (position, keys) state.
+    seen: Mutex<FxHashMap<(usize, u32), isize>>,
+    best: AtomicIsize,
+    /// States that have been offered, but not completely explored (queued or held by a worker).
+    outstanding: AtomicUsize,
+    queued: AtomicUsize,
+    explored: AtomicUsize,
+    duplicates: AtomicUsize,
+    /// The entries in `seen` are never removed, so they get a budget of their own. That way, a full
+    /// table can't keep states out of the queues, which would stop the other workers from stealing.
+    seen_memory: Budget,
+    queue_memory: Budget,
+    state_bytes: usize,
+    cancelled: AtomicBool,
+}
+
+impl Shared {
+    fn new(input: &[u8], options: &SearchOptions) -> Shared {
+        let state = State::new(input);
+        let state_bytes = std::mem::size_of::<State>() + state.maze.maze.capacity()
+            + state.maze.entrances.capacity() * std::mem::size_of::<usize>();
+        let shared = Shared {
+            queues: (0..options.threads.max(1)).map(|_| Mutex::new(VecDeque::new())).collect(),
+            seen: Mutex::new(FxHashMap::default()),
+            best: AtomicIsize::new(isize::MAX),
+            outstanding: AtomicUsize::new(0),
+            queued: AtomicUsize::new(0),
+            explored: AtomicUsize::new(0),
+            duplicates: AtomicUsize::new(0),
+            seen_memory: Budget::new(options.memory_budget / 2),
+            queue_memory: Budget::new(options.memory_budget - options.memory_budget / 2),
+            state_bytes,
+            cancelled: AtomicBool::new(false),
+        };
+        // doesn't count against the budget, so that there is always something to start with
+        shared.outstanding.fetch_add(1, Ordering::SeqCst);
+        shared.queued.fetch_add(1, Ordering::SeqCst);
+        shared.queues[0].lock().expect("queue lock").push_back(Queued { state, reserved: false });
+        shared
+    }
+
+    fn best(&self) -> Option<isize> {
+        Some(self.best.load(Ordering::SeqCst)).filter(|&b| b != isize::MAX)
+    }
+
+    fn progress(&self) -> Progress {
+        Progress {
+            best: self.best(),
+            queued: self.queued.load(Ordering::Relaxed),
+            explored: self.explored.load(Ordering::Relaxed),
+            duplicates: self.duplicates.load(Ordering::Relaxed),
+            memory_used: self.seen_memory.used() + self.queue_memory.used(),
+        }
+    }
+
+    /// Whether a cheaper (or equally cheap) equivalent state has been seen already. Otherwise
+    /// remembers `state` if `remember` is set and the budget allows.
+    fn is_duplicate(&self, state: &State, remember: bool) -> bool {
+        let key = (state.maze.player_idx, state.keys);
+        let mut seen = self.seen.lock().expect("seen lock");
+        let duplicate = match seen.get(&key) {
+            Some(&distance) if remember => distance <= state.total_distance,
+            Some(&distance) => distance < state.total_distance,
+            None => false,
+        };
+        if duplicate {
+            self.duplicates.fetch_add(1, Ordering::Relaxed);
+        } else if remember && (seen.contains_key(&key) || self.seen_memory.reserve(SEEN_ENTRY_BYTES)) {
+            seen.insert(key, state.total_distance);
+        }
+        duplicate
+    }
+
+    /// Queues a state that `worker` found, or hands it back via `local` if the budget is used up.
+    fn offer(&self, worker: usize, state: State, local: &mut Vec<State>) {
+        if state.total_distance >= self.best.load(Ordering::Relaxed) || self.is_duplicate(&state, true) {
+            return;
+        }
+        self.outstanding.fetch_add(1, Ordering::SeqCst);
+        if self.queue_memory.reserve(self.state_bytes) {
+            self.queued.fetch_add(1, Ordering::Relaxed);
+            self.queues[worker].lock().expect("queue lock").push_back(Queued { state, reserved: true });
+        } else {
+            local.push(state);
+        }
+    }
+
+    /// Newest state of the worker's own queue, otherwise the oldest state of another queue.
+    fn take(&self, worker: usize) -> Option<State> {
+        let own = self.queues[worker].lock().expect("queue lock").pop_back();
+        let queued = own.or_else(|| (1..self.queues.len())
+            .map(|offset| (worker + offset) % self.queues.len())
+            .find_map(|victim| self.queues[victim].lock().expect("queue lock").pop_front()))?;
+        self.queued.fetch_sub(1, Ordering::Relaxed);
+        if queued.reserved {
+            self.queue_memory.release(self.state_bytes);
+        }
+        Some(queued.state)
+    }
 
-            if shared_pool.queued_count() <= 1_000_000 {
-                for pending_state in pending_states.drain(..) {
-                    schedule_state(&shared_pool, pending_state, &low_score, &num_states_added, &num_states_explored);
+    /// Explores states until the search space is exhausted or the search gets cancelled.
+    fn work(&self, worker: usize) {
+        // states that didn't fit into the budget; explored depth first, which keeps this short
+        let mut local = Vec::new();
+        let mut pending = VecDeque::new();
+        while !self.cancelled.load(Ordering::Relaxed) {
+            let mut state = match local.pop().or_else(|| self.take(worker)) {
+                Some(state) => state,
+                None if self.outstanding.load(Ordering::SeqCst) == 0 => break,
+                None => {
+                    std::thread::sleep(Duration::from_millis(1));
+                    continue;
+                }
+            };
+            // a cheaper equivalent might have turned up since the state was offered
+            if !self.is_duplicate(&state, false) {
+                let score = state.explore(self.best.load(Ordering::Relaxed), &mut pending, |s| !self.is_duplicate(s, true));
+                self.best.fetch_min(score, Ordering::SeqCst);
+                self.explored.fetch_add(1, Ordering::Relaxed);
+                for found in pending.drain(..) {
+                    self.offer(worker, found, &mut local);
                 }
             }
+            self.outstanding.fetch_sub(1, Ordering::SeqCst);
         }
-    });
+    }
 }
 
-#[aoc(day18, part1)]
-pub fn part1(input: &Vec<u8>) -> isize {
-    let pool = Arc::new(ThreadPool::new(6));
-    let low_score = Arc::new(AtomicIsize::new(std::isize::MAX));
-    let num_states_added = Arc::new(AtomicUsize::new(0));
-    let  num_states_explored = Arc::new(AtomicUsize::new(0));
-    schedule_state(&pool, State { maze: Maze::new(input.clone()), total_distance: 0 }, &low_score, &num_states_added, &num_states_explored);
+/// Branch and bound search for the fewest steps needed to collect all keys, running on its own threads.
+pub struct Search {
+    shared: Arc<Shared>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl Search {
+    pub fn start(input: &[u8], options: SearchOptions) -> Search {
+        let shared = Arc::new(Shared::new(input, &options));
+        let workers = (0..shared.queues.len()).map(|worker| {
+            let shared = shared.clone();
+            std::thread::spawn(move || shared.work(worker))
+        }).collect();
+        Search { shared, workers }
+    }
+
+    pub fn progress(&self) -> Progress {
+        self.shared.progress()
+    }
 
-    let interval = 5;
-    while pool.queued_count() + pool.active_count() > 0 {
-        let low_score = low_score.load(Ordering::Relaxed);
-        let num_states_added = num_states_added.swap(0, Ordering::Relaxed);
-        let num_states_explored = num_states_explored.swap(0, Ordering::Relaxed);
+    /// Asks the workers to stop after the states they are currently exploring.
+    pub fn cancel(&self) {
+        self.shared.cancelled.store(true, Ordering::SeqCst);
+    }
 
-        eprintln!("Low score: {} Pending States: {} Explore {}/s Add {}/s", low_score, pool.queued_count(), num_states_explored / interval as usize, num_states_added / interval as usize);
-        std::thread::sleep(Duration::from_secs(interval));
+    pub fn is_finished(&self) -> bool {
+        self.workers.iter().all(|w| w.is_finished())
     }
 
-    low_score.load(Ordering::SeqCst)
+    /// Waits for the workers to finish.
+    pub fn wait(self) -> Outcome {
+        for worker in self.workers {
+            worker.join().expect("search worker panicked");
+        }
+        if self.shared.outstanding.load(Ordering::SeqCst) == 0 {
+            Outcome::Finished(self.shared.best())
+        } else {
+            Outcome::Cancelled(self.shared.progress())
+        }
+    }
+
+    /// Like `wait`, but calls `report` every `interval` until the search finishes. The search gets
+    /// cancelled as soon as `report` returns false.
+    pub fn wait_reporting(self, interval: Duration, mut report: impl FnMut(&Progress) -> bool) -> Outcome {
+        let mut last_report = Instant::now();
+        while !self.is_finished() {
+            std::thread::sleep(Duration::from_millis(1).min(interval));
+            if last_report.elapsed() >= interval {
+                last_report = Instant::now();
+                if !report(&self.progress()) {
+                    self.cancel();
+                    break;
+                }
+            }
+        }
+        self.wait()
+    }
 }
 
 #[derive(Clone)]
 struct State {
     maze: Maze,
     total_distance: isize,
+    /// Collected keys.
+    keys: u32,
 }
 
 impl State {
-    fn explore(&mut self, low_score: isize, pending_states: &mut VecDeque<State>) -> isize {
+    /// Starting state of the branch and bound search, which only knows how to move a single robot.
+    fn new(input: &[u8]) -> State {
+        let maze = Maze::new(input.to_vec());
+        assert_eq!(maze.entrances.len(), 1, "maze to contain a single @");
+        State { maze, total_distance: 0, keys: 0 }
+    }
+
+    /// Greedily walks to the closest key until all keys are collected, splitting off states for the other
+    /// keys. Gives up once the walk can't beat `low_score` or `is_new` rejects the state it got to.
+    fn explore(&mut self, low_score: isize, pending_states: &mut VecDeque<State>, mut is_new: impl FnMut(&State) -> bool) -> isize {
         let mut reachable_keys= Vec::new();
         loop {
             let limit = low_score - self.total_distance - 1;
             self.maze.flood(limit, &mut reachable_keys);
             if reachable_keys.is_empty() {
-                //eprintln!("Cannot reach last key in time, aborting state exploration");
-                return std::isize::MAX;
+                return isize::MAX;
             }
             let closest = reachable_keys.get(0).expect("moved into a dead end?!");
             if self.maze.num_keys_left == 1 {
@@ -119,13 +333,14 @@ impl State {
                 pending_states.extend(reachable_keys.iter().skip(1).map(|k| {
                     let mut state: State = self.clone();
                     state.move_to_key(k);
-                    //eprintln!("          Will need to try out key {} (distance {}, remaining {}) later", char::from(k.key), k.distance, state.maze.num_keys_left);
                     state
                 }));
 
                 // Continue with 'our' copy of the state
                 self.move_to_key(closest);
-                //eprintln!("Continuing with key {} (distance {}, remaining {}) now", char::from(closest.key), closest.distance, self.maze.num_keys_left);
+                if !is_new(self) {
+                    return isize::MAX;
+                }
             }
         }
     }
@@ -133,6 +348,7 @@ impl State {
     fn move_to_key(&mut self, candidate: &KeyCandidate) {
         self.maze.move_to_key(candidate.key);
         self.total_distance += candidate.distance;
+        self.keys |= key_bit(candidate.key);
     }
 }
 
@@ -143,6 +359,7 @@ struct Maze {
     // used for test assertions
     #[allow(unused)]
     height: isize,
+    entrances: Vec<usize>,
     // state
     player_idx: usize,
     num_keys_left: usize,
@@ -156,15 +373,16 @@ impl Maze {
             .map(|(i, _)| i)
             .next().expect("at least one line");
         let height = (maze.len() / stride) as isize + if maze.len() % stride == 0 { 0 } else { 1 };
-        let player_idx = maze.iter()
+        let entrances: Vec<usize> = maze.iter()
             .enumerate()
-            .find(|(_, x)| **x == b'@')
-            .expect("maze to contain @")
-            .0;
+            .filter(|(_, x)| **x == b'@')
+            .map(|(i, _)| i)
+            .collect();
+        let player_idx = *entrances.first().expect("maze to contain @");
         let num_keys_left = maze.iter()
             .filter(|x| b'a' <= **x && **x <= b'z')
             .count();
-        Maze { maze, stride, height, player_idx, num_keys_left }
+        Maze { maze, stride, height, entrances, player_idx, num_keys_left }
     }
 
     fn idx(&self, coord: (isize, isize)) -> usize {
@@ -253,6 +471,155 @@ impl std::fmt::Display for Maze {
     }
 }
 
+/// Walls off the single entrance and the tiles next to it, leaving one entrance in each diagonal
+/// corner of the 3x3 square around it. The result is four separate vaults.
+pub fn split_entrance(input: &[u8]) -> Vec<u8> {
+    let mut maze = Maze::new(input.to_vec());
+    assert_eq!(maze.entrances.len(), 1, "maze to contain a single @");
+    let (x, y) = maze.coord(maze.entrances[0]);
+    for dy in -1..=1 {
+        for dx in -1..=1 {
+            let idx = maze.idx((x + dx, y + dy));
+            if (dx, dy) != (0, 0) && maze.maze[idx] != b'.' {
+                panic!("expected open space around the entrance, found '{}' at {:?}", char::from(maze.maze[idx]), (x + dx, y + dy));
+            }
+            maze.maze[idx] = if dx != 0 && dy != 0 { b'@' } else { b'#' };
+        }
+    }
+    maze.maze
+}
+
+/// Bit for the key (or door) `c` in a set of keys.
+fn key_bit(c: u8) -> u32 {
+    1 << (c.to_ascii_lowercase() - b'a')
+}
+
+/// Number of possible keys. Graph nodes `0..NUM_KEYS` are the keys `a..z`, the entrances come after them.
+const NUM_KEYS: usize = 26;
+/// Node of the first entrance.
+const ENTRANCE: usize = NUM_KEYS;
+
+/// Shortest walk from an entrance or key to another key.
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+struct Walk {
+    to: usize,
+    distance: usize,
+    /// Doors on the way, as keys.
+    doors: u32,
+    /// Other keys on the way, which would be picked up.
+    keys: u32,
+}
+
+/// The maze reduced to the walks between its points of interest. Assumes that, as in the puzzle inputs,
+/// there is only one sensible way between any two points (a detour can't avoid a door).
+struct KeyGraph {
+    walks: Vec<Vec<Walk>>,
+    all_keys: u32,
+}
+
+impl KeyGraph {
+    fn new(maze: &Maze) -> KeyGraph {
+        let mut walks = vec![Vec::new(); ENTRANCE + maze.entrances.len()];
+        let mut all_keys = 0;
+        for (idx, &c) in maze.maze.iter().enumerate() {
+            if c.is_ascii_lowercase() {
+                all_keys |= key_bit(c);
+                walks[(c - b'a') as usize] = Self::walks_from(maze, idx);
+            }
+        }
+        for (i, &idx) in maze.entrances.iter().enumerate() {
+            walks[ENTRANCE + i] = Self::walks_from(maze, idx);
+        }
+        KeyGraph { walks, all_keys }
+    }
+
+    /// Breadth first search for every key reachable from `start`, ignoring doors (but noting them).
+    fn walks_from(maze: &Maze, start: usize) -> Vec<Walk> {
+        let mut walks = Vec::new();
+        let mut visited = vec![false; maze.maze.len()];
+        let mut pending = VecDeque::new();
+        visited[start] = true;
+        pending.push_back((start, 0, 0u32, 0u32));
+        while let Some((idx, distance, mut doors, mut keys)) = pending.pop_front() {
+            match maze.maze[idx] {
+                c @ b'A'..=b'Z' => doors |= key_bit(c),
+                c @ b'a'..=b'z' if idx != start => {
+                    walks.push(Walk { to: (c - b'a') as usize, distance, doors, keys });
+                    keys |= key_bit(c);
+                }
+                _ => ()
+            }
+            for adj_idx in maze.adjacent_iter(idx) {
+                let passable = maze.maze.get(adj_idx).is_some_and(|&c| c != b'#' && c != b'\n');
+                if passable && !visited[adj_idx] {
+                    visited[adj_idx] = true;
+                    pending.push_back((adj_idx, distance + 1, doors, keys));
+                }
+            }
+        }
+        walks
+    }
+}
+
+/// The fewest steps needed to collect all keys and the order in which they get collected.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct KeyRoute {
+    pub steps: usize,
+    pub keys: Vec<char>,
+}
+
+/// Dijkstra over (robot positions, collected keys) where every move walks one robot to a key that hasn't
+/// been collected yet. A move is only possible once the doors and all other keys on the way have been
+/// collected (by any robot); otherwise the route via the key on the way is just as long.
+/// There is one robot per entrance. `None` if some key can't be reached.
+pub fn shortest_route(input: &[u8]) -> Option<KeyRoute> {
+    let maze = Maze::new(input.to_vec());
+    assert!(ENTRANCE + maze.entrances.len() <= u8::MAX as usize + 1, "too many entrances");
+    let graph = KeyGraph::new(&maze);
+    type Node = (Vec<u8>, u32);
+    let start: Node = ((0..maze.entrances.len()).map(|i| (ENTRANCE + i) as u8).collect(), 0);
+    let mut distances: FxHashMap<Node, usize> = FxHashMap::default();
+    let mut predecessors: FxHashMap<Node, Node> = FxHashMap::default();
+    let mut pending = BinaryHeap::new();
+    distances.insert(start.clone(), 0);
+    pending.push(Reverse((0, start.clone())));
+    while let Some(Reverse((distance, node))) = pending.pop() {
+        let keys = node.1;
+        if keys == graph.all_keys {
+            let mut route = Vec::new();
+            let mut current = &node;
+            while *current != start {
+                let previous = &predecessors[current];
+                route.push((b'a' + (current.1 ^ previous.1).trailing_zeros() as u8) as char);
+                current = previous;
+            }
+            route.reverse();
+            return Some(KeyRoute { steps: distance, keys: route });
+        }
+        if distances.get(&node).is_some_and(|&d| d < distance) {
+            continue;
+        }
+        for (robot, &position) in node.0.iter().enumerate() {
+            for walk in &graph.walks[position as usize] {
+                let target = 1 << walk.to;
+                if keys & target != 0 || (walk.doors | walk.keys) & !keys != 0 {
+                    continue;
+                }
+                let mut positions = node.0.clone();
+                positions[robot] = walk.to as u8;
+                let next = (positions, keys | target);
+                let next_distance = distance + walk.distance;
+                if distances.get(&next).is_none_or(|&d| next_distance < d) {
+                    distances.insert(next.clone(), next_distance);
+                    predecessors.insert(next.clone(), node.clone());
+                    pending.push(Reverse((next_distance, next)));
+                }
+            }
+        }
+    }
+    None
+}
+
 
 #[allow(unused)]
 #[cfg(test)]
@@ -333,13 +700,244 @@ mod tests {
 #########
 #b.A.@.a#
 #########"));
-        let mut state = State { maze, total_distance: 0 };
+        let mut state = State { maze, total_distance: 0, keys: 0 };
         let mut pending_states = VecDeque::new();
-        let low_score = state.explore(1000, &mut pending_states);
+        let low_score = state.explore(1000, &mut pending_states, |_| true);
         assert_eq!(low_score, 8);
         assert_eq!(pending_states.len(), 0);
     }
 
+    #[test]
+    fn key_graph_walks() {
+        let graph = KeyGraph::new(&Maze::new(generator("\
+#########
+#b.A.@.a#
+#########")));
+        assert_eq!(graph.all_keys, 0b11);
+        assert_eq!(graph.walks[ENTRANCE], vec![
+            Walk { to: 0, distance: 2, doors: 0, keys: 0 },
+            Walk { to: 1, distance: 4, doors: 0b1, keys: 0 },
+        ]);
+        assert_eq!(graph.walks[0], vec![Walk { to: 1, distance: 6, doors: 0b1, keys: 0 }]);
+    }
+
+    #[test]
+    fn shortest_route_examples() {
+        let route = |maze: &str| shortest_route(&generator(maze)).expect("route exists");
+        assert_eq!(route("\
+#########
+#b.A.@.a#
+#########"), KeyRoute { steps: 8, keys: vec!['a', 'b'] });
+        assert_eq!(route("\
+########################
+#f.D.E.e.C.b.A.@.a.B.c.#
+######################.#
+#d.....................#
+########################"), KeyRoute { steps: 86, keys: "abcdef".chars().collect() });
+        assert_eq!(route("\
+########################
+#...............b.C.D.f#
+#.######################
+#.....@.a.B.c.d.A.e.F.g#
+########################"), KeyRoute { steps: 132, keys: "bacdfeg".chars().collect() });
+        assert_eq!(route("\
+########################
+#@..............ac.GI.b#
+###d#e#f################
+###A#B#C################
+###g#h#i################
+########################"), KeyRoute { steps: 81, keys: "acfidgbeh".chars().collect() });
+    }
+
+    #[test]
+    fn shortest_route_edge_cases() {
+        assert_eq!(shortest_route(&generator("#####\n#.@.#\n#####")), Some(KeyRoute { steps: 0, keys: vec![] }));
+        // walled off
+        assert_eq!(shortest_route(&generator("#####\n#@#a#\n#####")), None);
+        // door without a key
+        assert_eq!(shortest_route(&generator("#######\n#@.B.a#\n#######")), None);
+    }
+
+    #[test]
+    fn multiple_entrances() {
+        let m = Maze::new(generator("#####\n#@#@#\n#####"));
+        assert_eq!(m.entrances, vec![7, 9]);
+        assert_eq!(m.coord(m.player_idx), (1, 1));
+    }
+
+    #[test]
+    fn split_entrance_vaults() {
+        let split = split_entrance(&generator("\
+#######
+#a.#Cd#
+##...##
+##.@.##
+##...##
+#cB#Ab#
+#######"));
+        assert_eq!(String::from_utf8(split).unwrap(), "\
+#######
+#a.#Cd#
+##@#@##
+#######
+##@#@##
+#cB#Ab#
+#######");
+    }
+
+    #[test]
+    #[should_panic(expected = "expected open space around the entrance, found 'a' at (3, 2)")]
+    fn split_entrance_blocked() {
+        split_entrance(&generator("#####\n#...#\n#.@a#\n#...#\n#####"));
+    }
+
+    #[test]
+    fn part2_example_split() {
+        assert_eq!(part2(&generator("\
+#######
+#a.#Cd#
+##...##
+##.@.##
+##...##
+#cB#Ab#
+#######")), 8);
+    }
+
+    #[test]
+    fn part2_examples() {
+        assert_eq!(part2(&generator("\
+###############
+#d.ABC.#.....a#
+######@#@######
+###############
+######@#@######
+#b.....#.....c#
+###############")), 24);
+        assert_eq!(part2(&generator("\
+#############
+#DcBa.#.GhKl#
+#.###@#@#I###
+#e#d#####j#k#
+###C#@#@###J#
+#fEbA.#.FgHi#
+#############")), 32);
+        assert_eq!(part2(&generator("\
+#############
+#g#f.D#..h#l#
+#F###e#E###.#
+#dCba@#@BcIJ#
+#############
+#nK.L@#@G...#
+#M###N#H###.#
+#o#m..#i#jk.#
+#############")), 72);
+    }
+
+    #[test]
+    fn shortest_route_four_robots() {
+        let route = shortest_route(&generator("\
+###############
+#d.ABC.#.....a#
+######@#@######
+###############
+######@#@######
+#b.....#.....c#
+###############")).unwrap();
+        assert_eq!(route, KeyRoute { steps: 24, keys: vec!['a', 'b', 'c', 'd'] });
+    }
+
+    const EXAMPLE_2: &str = "\
+#################
+#i.G..c...e..H.p#
+########.########
+#j.A..b...f..D.o#
+########@########
+#k.E..a...g..B.n#
+########.########
+#l.F..d...h..C.m#
+#################";
+
+    #[test]
+    fn search_examples() {
+        let options = SearchOptions { threads: 2, ..SearchOptions::default() };
+        for (maze, expected) in [("#########\n#b.A.@.a#\n#########", 8), (EXAMPLE_2, 136)] {
+            let search = Search::start(&generator(maze), options);
+            assert_eq!(search.wait(), Outcome::Finished(Some(expected)));
+        }
+        let unreachable = Search::start(&generator("#####\n#@#a#\n#####"), options);
+        assert_eq!(unreachable.wait(), Outcome::Finished(None));
+    }
+
+    #[test]
+    fn search_deduplicates() {
+        let shared = Shared::new(&generator(EXAMPLE_2), &SearchOptions { threads: 1, memory_budget: 64 << 20 });
+        shared.work(0);
+        let progress = shared.progress();
+        assert_eq!(progress.best, Some(136));
+        assert_eq!(progress.queued, 0);
+        assert!(progress.duplicates > 0, "{:?}", progress);
+        assert!(0 < progress.memory_used && progress.memory_used <= 64 << 20, "{:?}", progress);
+        // everything queued has been taken again, only the table of seen states remains
+        assert_eq!(shared.queue_memory.used(), 0);
+        assert_eq!(progress.memory_used, shared.seen_memory.used());
+    }
+
+    #[test]
+    fn full_seen_table_keeps_queueing() {
+        let maze = generator("#########\n#b.A.@.a#\n#########");
+        let shared = Shared::new(&maze, &SearchOptions { threads: 2, memory_budget: 1 << 20 });
+        while shared.seen_memory.reserve(SEEN_ENTRY_BYTES) {}
+        let mut state = State::new(&maze);
+        state.move_to_key(&KeyCandidate { key: b'a', distance: 2 });
+        let mut local = Vec::new();
+        shared.offer(0, state, &mut local);
+        assert!(local.is_empty());
+        assert_eq!(shared.progress().queued, 2);
+        assert_eq!(shared.queue_memory.used(), shared.state_bytes);
+        // the other worker steals the oldest state first: the initial one, which never reserved anything
+        assert_eq!(shared.take(1).map(|s| s.keys), Some(0));
+        assert_eq!(shared.queue_memory.used(), shared.state_bytes);
+        assert_eq!(shared.take(1).map(|s| s.keys), Some(1));
+        assert_eq!(shared.queue_memory.used(), 0);
+    }
+
+    #[test]
+    fn search_without_budget() {
+        // nothing fits, so everything gets explored depth first by the worker that found it
+        let shared = Shared::new(&generator("\
+########################
+#f.D.E.e.C.b.A.@.a.B.c.#
+######################.#
+#d.....................#
+########################"), &SearchOptions { threads: 1, memory_budget: 0 });
+        shared.work(0);
+        let progress = shared.progress();
+        assert_eq!(progress.best, Some(86));
+        assert_eq!((progress.duplicates, progress.memory_used), (0, 0));
+    }
+
+    #[test]
+    fn search_cancel() {
+        // 26 keys in an open room: far too many orders to run out of states before the limit
+        let room = generator("\
+###############################
+#a.b.c.d.e.f.g.h.i.j.k.l.m....#
+#.............@...............#
+#n.o.p.q.r.s.t.u.v.w.x.y.z....#
+###############################");
+        let search = Search::start(&room, SearchOptions { threads: 2, memory_budget: 1 << 20 });
+        let mut reports = 0;
+        let outcome = search.wait_reporting(Duration::from_millis(1), |progress| {
+            reports += 1;
+            progress.explored < 100
+        });
+        assert!(reports > 0);
+        match outcome {
+            Outcome::Cancelled(progress) => assert!(progress.explored >= 100, "{:?}", progress),
+            other => panic!("unexpected outcome {:?}", other),
+        }
+    }
+
     #[test]
     fn part1_simple() {
         let input = generator("\
@@ -1,20 +1,25 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
 use std::str::FromStr;
+use fxhash::{FxHashMap, FxHashSet};
+use itertools::Itertools;
 
+/// One wire per non-empty line.
 #[aoc_generator(day3)]
-pub fn generator(input: &str) -> (Vec<Segment>, Vec<Segment>) {
-    let raw_lines: Vec<Vec<Segment>> = input
+pub fn generator(input: &str) -> Vec<Wire> {
+    input
         .lines()
+        .filter(|line| !line.trim().is_empty())
         .map(|line| serde_scan::from_str_skipping::<Vec<String>>(",", line)
             .expect("comma separated line directions")
             .into_iter()
             .map(Segment::from)
             .collect())
-        .take(2)
-        .collect();
-    let mut d = raw_lines.into_iter();
-    (d.next().expect("first line"), d.next().expect("second line"))
+        .collect()
 }
 
+pub type Wire = Vec<Segment>;
+
 #[derive(Copy, Clone, Eq, PartialEq, Debug)]
 pub enum Segment {
     U(isize),
@@ -76,8 +81,17 @@ fn min_norm_1(lhs: Pos, rhs: Pos) -> Pos {
     }
 }
 
+/// The grid solvers only support exactly two wires.
+fn two_wires(input: &[Wire]) -> (&Wire, &Wire) {
+    match input {
+        [first, second] => (first, second),
+        _ => panic!("grid solver supports exactly two wires, got {}", input.len())
+    }
+}
+
 #[aoc(day3, part1, bool_grid)]
-pub fn part1(input: &(Vec<Segment>, Vec<Segment>)) -> isize {
+pub fn part1(input: &Vec<Wire>) -> isize {
+    let input = two_wires(input);
     let mut grid = vec![false; (STRIDE * STRIDE) as usize];
 
     let mut cursor = (0, 0);
@@ -109,7 +123,8 @@ pub fn part1(input: &(Vec<Segment>, Vec<Segment>)) -> isize {
 type Distance = u16;
 
 #[aoc(day3, part2, dist_grid)]
-pub fn part2(input: &(Vec<Segment>, Vec<Segment>)) -> Distance {
+pub fn part2(input: &Vec<Wire>) -> Distance {
+    let input = two_wires(input);
     let mut grid = vec![0 as Distance; (STRIDE * STRIDE) as usize];
 
     let mut cursor = (0, 0);
@@ -143,6 +158,227 @@ pub fn part2(input: &(Vec<Segment>, Vec<Segment>)) -> Distance {
     closest
 }
 
+/// Horizontal or vertical piece of a wire: `fixed` is the coordinate that stays the same, `from..=to`
+/// the range along the other axis. `steps` is the wire length up to `start`.
+#[derive(Copy, Clone, Debug)]
+struct Span {
+    fixed: isize,
+    from: isize,
+    to: isize,
+    wire: usize,
+    start: Pos,
+    steps: isize,
+}
+
+impl Span {
+    fn steps_to(&self, pos: Pos) -> isize {
+        self.steps + (pos.0 - self.start.0).abs() + (pos.1 - self.start.1).abs()
+    }
+}
+
+/// Splits the wires into (horizontal, vertical) spans.
+fn spans<W: AsRef<[Segment]>>(wires: &[W]) -> (Vec<Span>, Vec<Span>) {
+    let mut horizontal = Vec::new();
+    let mut vertical = Vec::new();
+    for (wire, segments) in wires.iter().enumerate() {
+        let mut cursor: Pos = (0, 0);
+        let mut steps = 0;
+        for segment in segments.as_ref() {
+            let (end, distance) = match *segment {
+                Segment::U(d) => ((cursor.0, cursor.1 + d), d),
+                Segment::D(d) => ((cursor.0, cursor.1 - d), d),
+                Segment::R(d) => ((cursor.0 + d, cursor.1), d),
+                Segment::L(d) => ((cursor.0 - d, cursor.1), d),
+            };
+            let span = |fixed, a: isize, b: isize| Span { fixed, from: a.min(b), to: a.max(b), wire, start: cursor, steps };
+            match segment {
+                Segment::U(_) | Segment::D(_) => vertical.push(span(cursor.0, cursor.1, end.1)),
+                Segment::L(_) | Segment::R(_) => horizontal.push(span(cursor.1, cursor.0, end.0)),
+            }
+            cursor = end;
+            steps += distance;
+        }
+    }
+    (horizontal, vertical)
+}
+
+/// A point (other than the origin) where at least two different wires meet.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct Crossing {
+    pub position: Pos,
+    /// Wire index → number of steps that wire needs to first reach the crossing.
+    pub steps: BTreeMap<usize, isize>,
+}
+
+impl Crossing {
+    /// Manhattan distance from the origin.
+    pub fn distance(&self) -> isize {
+        norm_1(self.position)
+    }
+
+    /// Combined steps of all wires that meet here.
+    pub fn total_steps(&self) -> isize {
+        self.steps.values().sum()
+    }
+}
+
+type Incidences = FxHashMap<Pos, BTreeMap<usize, isize>>;
+
+fn record(incidences: &mut Incidences, pos: Pos, a: &Span, b: &Span) {
+    if pos == (0, 0) || a.wire == b.wire {
+        return;
+    }
+    let wires = incidences.entry(pos).or_default();
+    for span in [a, b].iter() {
+        let steps = span.steps_to(pos);
+        let entry = wires.entry(span.wire).or_insert(steps);
+        *entry = (*entry).min(steps);
+    }
+}
+
+/// Overlaps between spans on the same line, which cross at every point of the overlap.
+fn collinear_overlaps(spans: &mut [Span], point: fn(isize, isize) -> Pos, incidences: &mut Incidences) {
+    spans.sort_by_key(|s| (s.fixed, s.from));
+    for (i, span) in spans.iter().enumerate() {
+        for earlier in spans[..i].iter().rev().take_while(|e| e.fixed == span.fixed) {
+            for along in span.from..=earlier.to.min(span.to) {
+                record(incidences, point(span.fixed, along), span, earlier);
+            }
+        }
+    }
+}
+
+/// All points where two or more wires meet, ordered by position. Perpendicular spans are
+/// intersected with a sweep line along x that keeps the horizontal spans it currently touches
+/// ordered by y, so only pairs that actually cross are ever visited.
+pub fn crossings<W: AsRef<[Segment]>>(wires: &[W]) -> Vec<Crossing> {
+    let (mut horizontal, mut vertical) = spans(wires);
+    let mut incidences = Incidences::default();
+
+    #[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
+    enum Event { Enter, Vertical, Leave }
+    let mut events: Vec<(isize, Event, usize)> = Vec::with_capacity(2 * horizontal.len() + vertical.len());
+    events.extend(horizontal.iter().enumerate().flat_map(|(i, h)| vec![(h.from, Event::Enter, i), (h.to, Event::Leave, i)]));
+    events.extend(vertical.iter().enumerate().map(|(i, v)| (v.fixed, Event::Vertical, i)));
+    events.sort();
+
+    let mut active: BTreeSet<(isize, usize)> = BTreeSet::new();
+    for (x, event, i) in events {
+        match event {
+            Event::Enter => { active.insert((horizontal[i].fixed, i)); }
+            Event::Leave => { active.remove(&(horizontal[i].fixed, i)); }
+            Event::Vertical => {
+                let v = &vertical[i];
+                for &(y, h) in active.range((v.from, 0)..=(v.to, usize::MAX)) {
+                    record(&mut incidences, (x, y), v, &horizontal[h]);
+                }
+            }
+        }
+    }
+
+    collinear_overlaps(&mut horizontal, |y, x| (x, y), &mut incidences);
+    collinear_overlaps(&mut vertical, |x, y| (x, y), &mut incidences);
+
+    let mut crossings: Vec<Crossing> = incidences.into_iter()
+        .map(|(position, steps)| Crossing { position, steps })
+        .collect();
+    crossings.sort_by_key(|c| c.position);
+    crossings
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct ClosestCrossings {
+    /// Crossing closest to the origin by Manhattan distance.
+    pub by_distance: Crossing,
+    /// Crossing with the fewest combined steps.
+    pub by_steps: Crossing,
+}
+
+/// `None` if the wires never cross.
+pub fn closest_crossings<W: AsRef<[Segment]>>(wires: &[W]) -> Option<ClosestCrossings> {
+    let crossings = crossings(wires);
+    let by_distance = crossings.iter().min_by_key(|c| c.distance())?.clone();
+    let by_steps = crossings.iter().min_by_key(|c| c.total_steps())?.clone();
+    Some(ClosestCrossings { by_distance, by_steps })
+}
+
+#[aoc(day3, part1, sweep)]
+pub fn part1_sweep(input: &Vec<Wire>) -> isize {
+    closest_crossings(input).expect("wires cross").by_distance.distance()
+}
+
+#[aoc(day3, part2, sweep)]
+pub fn part2_sweep(input: &Vec<Wire>) -> isize {
+    closest_crossings(input).expect("wires cross").by_steps.total_steps()
+}
+
+impl fmt::Display for Crossing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let wires: Vec<String> = self.steps.keys().map(|w| w.to_string()).collect();
+        let steps: Vec<String> = self.steps.values().map(|s| s.to_string()).collect();
+        write!(f, "({}, {}): wires {}, steps {} = {}, distance {}",
+               self.position.0, self.position.1, wires.join("+"), steps.join("+"), self.total_steps(), self.distance())
+    }
+}
+
+/// One line per crossing (see `Crossing`'s `Display`), ordered by position.
+pub fn crossing_report<W: AsRef<[Segment]>>(wires: &[W]) -> String {
+    crossings(wires).iter().map(|c| format!("{}\n", c)).collect()
+}
+
+/// Renders the panel in the style of the puzzle description: `o` is the central port, `-`/`|` wires,
+/// `+` corners and `X` points where different wires meet. North is up. Returns `None` if the panel
+/// (with a margin of one cell) would have more than `MAX_RENDER_CELLS` cells.
+pub fn render<W: AsRef<[Segment]>>(wires: &[W]) -> Option<String> {
+    let mut cells: FxHashMap<Pos, (char, usize)> = FxHashMap::default();
+    let mut crossed: FxHashSet<Pos> = FxHashSet::default();
+    for (wire, segments) in wires.iter().enumerate() {
+        let segments = segments.as_ref();
+        let mut cursor = (0, 0);
+        for (i, segment) in segments.iter().enumerate() {
+            let line = match segment { Segment::U(_) | Segment::D(_) => '|', Segment::L(_) | Segment::R(_) => '-' };
+            for step in segment.steps(cursor) {
+                cursor = step;
+                let symbol = match cells.get(&cursor) {
+                    Some(&(_, other)) if other != wire => {
+                        crossed.insert(cursor);
+                        line
+                    }
+                    Some(&(existing, _)) if existing != line => '+',
+                    _ => line,
+                };
+                cells.insert(cursor, (symbol, wire));
+            }
+            if i + 1 < segments.len() {
+                cells.insert(cursor, ('+', wire));
+            }
+        }
+    }
+
+    let points = || cells.keys().chain(std::iter::once(&(0, 0)));
+    let (min_x, max_x) = points().map(|p| p.0).minmax().into_option()?;
+    let (min_y, max_y) = points().map(|p| p.1).minmax().into_option()?;
+    let (width, height) = ((max_x - min_x + 3) as usize, (max_y - min_y + 3) as usize);
+    if width.checked_mul(height)? > MAX_RENDER_CELLS {
+        return None;
+    }
+    let mut out = String::with_capacity((width + 1) * height);
+    for y in ((min_y - 1)..=(max_y + 1)).rev() {
+        for x in (min_x - 1)..=(max_x + 1) {
+            out.push(match ((x, y), cells.get(&(x, y))) {
+                ((0, 0), _) => 'o',
+                (pos, Some(_)) if crossed.contains(&pos) => 'X',
+                (_, Some(&(symbol, _))) => symbol,
+                (_, None) => '.',
+            });
+        }
+        out.push('\n');
+    }
+    Some(out)
+}
+
+pub const MAX_RENDER_CELLS: usize = 200 * 200;
+
 
 #[cfg(test)]
 mod tests {
@@ -160,10 +396,9 @@ mod tests {
     #[test]
     fn parse_short_line() {
         let input = "R8,U5,L5,D3\nR8,U5,L5,D3\n";
-        let (line1, line2) = generator(input);
+        let lines = generator(input);
 
-        assert_eq!(line1, vec![R(8), U(5), L(5), D(3)]);
-        assert_eq!(line2, vec![R(8), U(5), L(5), D(3)]);
+        assert_eq!(lines, vec![vec![R(8), U(5), L(5), D(3)], vec![R(8), U(5), L(5), D(3)]]);
     }
 
     #[test]
@@ -215,4 +450,91 @@ mod tests {
         let lines = generator(input);
         assert_eq!(part2(&lines), 410);
     }
+
+    #[test]
+    fn sweep_examples() {
+        for (input, distance, steps) in [
+            ("R8,U5,L5,D3\nU7,R6,D4,L4\n", 6, 30),
+            ("R75,D30,R83,U83,L12,D49,R71,U7,L72\nU62,R66,U55,R34,D71,R55,D58,R83\n", 159, 610),
+            ("R98,U47,R26,D63,R33,U87,L62,D20,R33,U53,R51\nU98,R91,D20,R16,D67,R40,U7,R15,U6,R7\n", 135, 410),
+        ] {
+            let lines = generator(input);
+            assert_eq!(part1_sweep(&lines), distance, "{}", input);
+            assert_eq!(part2_sweep(&lines), steps, "{}", input);
+        }
+    }
+
+    #[test]
+    fn sweep_crossing_details() {
+        let wires = [vec![R(8), U(5), L(5), D(3)], vec![U(7), R(6), D(4), L(4)]];
+        let crossings = crossings(&wires);
+        let positions: Vec<Pos> = crossings.iter().map(|c| c.position).collect();
+        assert_eq!(positions, vec![(3, 3), (6, 5)]);
+        assert_eq!(crossings[0].steps.iter().map(|(w, s)| (*w, *s)).collect::<Vec<_>>(), vec![(0, 20), (1, 20)]);
+        let closest = closest_crossings(&wires).unwrap();
+        assert_eq!(closest.by_distance.position, (3, 3));
+        assert_eq!(closest.by_steps.position, (6, 5));
+    }
+
+    #[test]
+    fn sweep_collinear_and_self_crossings() {
+        // wire 0 crosses itself at (2, 0) - that doesn't count; wire 1 runs along wire 0 from (1, 0) to (3, 0)
+        let wires = [vec![R(4), U(1), L(2), D(2)], vec![D(1), R(1), U(1), R(2)]];
+        let positions: Vec<Pos> = crossings(&wires).iter().map(|c| c.position).collect();
+        assert_eq!(positions, vec![(1, 0), (2, 0), (3, 0)]);
+        // wire 0 reaches (2, 0) after 2 steps on its first pass, not 8 on the second
+        let at_2 = crossings(&wires).into_iter().find(|c| c.position == (2, 0)).unwrap();
+        assert_eq!(at_2.steps.get(&0), Some(&2));
+        assert_eq!(at_2.steps.get(&1), Some(&4));
+    }
+
+    #[test]
+    fn sweep_three_wires() {
+        let wires = [vec![R(4)], vec![U(2), R(2), D(4)], vec![D(1), R(3), U(2)]];
+        let crossings = crossings(&wires);
+        let summary: Vec<(Pos, Vec<usize>)> = crossings.iter().map(|c| (c.position, c.steps.keys().cloned().collect())).collect();
+        assert_eq!(summary, vec![((2, -1), vec![1, 2]), ((2, 0), vec![0, 1]), ((3, 0), vec![0, 2])]);
+        assert_eq!(closest_crossings(&wires).unwrap().by_distance.position, (2, 0));
+    }
+
+    #[test]
+    fn sweep_no_crossing() {
+        assert_eq!(closest_crossings(&[vec![R(3)], vec![L(3)]]), None);
+    }
+
+    #[test]
+    fn parse_many_lines() {
+        let lines = generator("R1\nU2\n\nL3\n");
+        assert_eq!(lines, vec![vec![R(1)], vec![U(2)], vec![L(3)]]);
+        assert_eq!(part1_sweep(&generator("R4\nU2,R2,D4\nD1,R3,U2")), 2);
+    }
+
+    #[test]
+    fn report_example_1() {
+        let lines = generator("R8,U5,L5,D3\nU7,R6,D4,L4\n");
+        assert_eq!(crossing_report(&lines), "(3, 3): wires 0+1, steps 20+20 = 40, distance 6
+(6, 5): wires 0+1, steps 15+15 = 30, distance 11
+");
+    }
+
+    #[test]
+    fn render_example_1() {
+        let lines = generator("R8,U5,L5,D3\nU7,R6,D4,L4\n");
+        assert_eq!(render(&lines).unwrap(), "...........
+.+-----+...
+.|.....|...
+.|..+--X-+.
+.|..|..|.|.
+.|.-X--+.|.
+.|..|....|.
+.|.......|.
+.o-------+.
+...........
+");
+    }
+
+    #[test]
+    fn render_too_large() {
+        assert_eq!(render(&[vec![R(1000)], vec![U(1000)]]), None);
+    }
 }
\ No newline at end of file
@@ -1,4 +1,5 @@
 use std::ops::{RangeInclusive};
+use fxhash::FxHashMap;
 
 type N = usize;
 
@@ -119,6 +120,144 @@ pub fn part2_naive(input: &RangeInclusive<N>) -> N {
     input.clone().filter(|n| has_exactly_double_digits(*n)).filter(|n| has_no_decreasing_digits(*n)).count()
 }
 
+/// Requirement on the maximal runs of equal adjacent digits.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Run {
+    /// At least one run is exactly this long.
+    Exactly(usize),
+    /// At least one run is at least this long.
+    AtLeast(usize),
+}
+
+impl Run {
+    fn accepts(self, len: usize) -> bool {
+        match self {
+            Run::Exactly(k) => len == k,
+            Run::AtLeast(k) => len >= k,
+        }
+    }
+
+    /// Run lengths beyond this don't make a difference.
+    fn cap(self) -> usize {
+        match self {
+            Run::Exactly(k) => k + 1,
+            Run::AtLeast(k) => k,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Rules {
+    pub non_decreasing: bool,
+    pub run: Option<Run>,
+}
+
+impl Rules {
+    pub const PART1: Rules = Rules { non_decreasing: true, run: Some(Run::AtLeast(2)) };
+    pub const PART2: Rules = Rules { non_decreasing: true, run: Some(Run::Exactly(2)) };
+
+    /// Checks a single number (written without leading zeros).
+    pub fn matches(&self, n: u64) -> bool {
+        let digits = n.to_string().into_bytes();
+        if self.non_decreasing && digits.windows(2).any(|w| w[1] < w[0]) {
+            return false;
+        }
+        match self.run {
+            None => true,
+            Some(run) => digits.chunk_by(|a, b| a == b).any(|r| run.accepts(r.len())),
+        }
+    }
+
+    fn run_cap(&self) -> usize {
+        self.run.map_or(1, Run::cap)
+    }
+
+    fn run_done(&self, len: usize) -> bool {
+        self.run.is_none_or(|run| run.accepts(len))
+    }
+}
+
+/// Digit DP over the digits of an upper bound. Once a prefix is below the bound ("not tight"), the
+/// number of completions only depends on the position, the last digit, the length of the current
+/// run and whether the run rule has already been satisfied, so those are memoized.
+struct DigitCounter<'a> {
+    digits: Vec<u8>,
+    rules: &'a Rules,
+    memo: FxHashMap<(usize, u8, usize, bool), u64>,
+}
+
+impl<'a> DigitCounter<'a> {
+    /// `last` is `None` while only leading zeros have been placed.
+    fn count(&mut self, pos: usize, tight: bool, last: Option<u8>, run: usize, satisfied: bool) -> u64 {
+        if pos == self.digits.len() {
+            return match last {
+                // only leading zeros: that's the number 0
+                None => self.rules.matches(0) as u64,
+                Some(_) => (satisfied || self.rules.run_done(run)) as u64,
+            };
+        }
+        let key = (pos, last.unwrap_or(0), run, satisfied);
+        if !tight && last.is_some() {
+            if let Some(&count) = self.memo.get(&key) {
+                return count;
+            }
+        }
+
+        let max = if tight { self.digits[pos] } else { 9 };
+        let mut count = 0;
+        for d in 0..=max {
+            let tight = tight && d == max;
+            count += match last {
+                None if d == 0 => self.count(pos + 1, tight, None, 0, false),
+                None => self.count(pos + 1, tight, Some(d), 1, false),
+                Some(l) if self.rules.non_decreasing && d < l => 0,
+                Some(l) if d == l => self.count(pos + 1, tight, last, (run + 1).min(self.rules.run_cap()), satisfied),
+                Some(_) => {
+                    let satisfied = satisfied || self.rules.run_done(run);
+                    self.count(pos + 1, tight, Some(d), 1, satisfied)
+                }
+            };
+        }
+
+        if !tight && last.is_some() {
+            self.memo.insert(key, count);
+        }
+        count
+    }
+}
+
+/// Number of `n` in `0..=max` that satisfy the rules.
+fn count_up_to(max: u64, rules: &Rules) -> u64 {
+    let digits = max.to_string().bytes().map(|b| b - b'0').collect();
+    DigitCounter { digits, rules, memo: FxHashMap::default() }.count(0, true, None, 0, false)
+}
+
+/// Number of passwords in `range` that satisfy the rules, in time polynomial in the number of digits.
+pub fn count_passwords(range: &RangeInclusive<u64>, rules: &Rules) -> u64 {
+    if range.start() > range.end() {
+        return 0;
+    }
+    let below = match range.start() {
+        0 => 0,
+        start => count_up_to(start - 1, rules),
+    };
+    count_up_to(*range.end(), rules) - below
+}
+
+fn to_u64(input: &RangeInclusive<N>) -> RangeInclusive<u64> {
+    (*input.start() as u64)..=(*input.end() as u64)
+}
+
+#[aoc(day4, part1, digit_dp)]
+pub fn part1_digit_dp(input: &RangeInclusive<N>) -> u64 {
+    count_passwords(&to_u64(input), &Rules::PART1)
+}
+
+#[aoc(day4, part2, digit_dp)]
+pub fn part2_digit_dp(input: &RangeInclusive<N>) -> u64 {
+    count_passwords(&to_u64(input), &Rules::PART2)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -242,4 +381,45 @@ mod tests {
         let input = 123456..=123480;
         assert_eq!(part1_naive(&input), 2);
     }
+
+    #[test]
+    fn rules_match_existing_checks() {
+        for n in (100_000..1_000_000).step_by(7) {
+            let decreasing = has_no_decreasing_digits(n);
+            assert_eq!(Rules::PART1.matches(n as u64), decreasing && has_double_digits(n), "part1 {}", n);
+            assert_eq!(Rules::PART2.matches(n as u64), decreasing && has_exactly_double_digits(n), "part2 {}", n);
+        }
+    }
+
+    #[test]
+    fn digit_dp_matches_brute_force() {
+        let all_rules: Vec<Rules> = vec![true, false].into_iter()
+            .flat_map(|non_decreasing| vec![None, Some(Run::Exactly(1)), Some(Run::Exactly(2)), Some(Run::Exactly(3)), Some(Run::AtLeast(2)), Some(Run::AtLeast(3))]
+                .into_iter()
+                .map(move |run| Rules { non_decreasing, run }))
+            .collect();
+        for rules in &all_rules {
+            for range in [0..=0, 0..=9, 0..=12_345, 99..=1001, 11_110..=11_112, 54_321..=98_765, RangeInclusive::new(5, 4)] {
+                let expected = range.clone().filter(|n| rules.matches(*n)).count() as u64;
+                assert_eq!(count_passwords(&range, rules), expected, "{:?} {:?}", rules, range);
+            }
+        }
+    }
+
+    #[test]
+    fn digit_dp_examples() {
+        assert_eq!(part1_digit_dp(&(123456..=123470)), 1);
+        assert_eq!(part1_digit_dp(&(123456..=123480)), 2);
+    }
+
+    #[test]
+    fn digit_dp_twelve_digits() {
+        // non-decreasing 12 digit numbers can't contain a 0: multisets of 12 digits from 1..=9
+        let only_non_decreasing = Rules { non_decreasing: true, run: None };
+        assert_eq!(count_passwords(&(100_000_000_000..=999_999_999_999), &only_non_decreasing), 125_970);
+        // ... and by pigeonhole, each of them has a run of at least 2
+        let part1 = count_passwords(&(100_000_000_000..=999_999_999_999), &Rules::PART1);
+        assert_eq!(part1, 125_970);
+        assert!(count_passwords(&(0..=u64::MAX), &Rules::PART2) > 0);
+    }
 }
\ No newline at end of file
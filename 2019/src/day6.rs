@@ -1,5 +1,9 @@
 use std::collections::hash_map::Entry;
-use fxhash::FxHashMap;
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, Write};
+use fxhash::{FxHashMap, FxHashSet};
+use crate::dot::quote;
 
 #[aoc_generator(day6)]
 pub fn generator(input: &str) -> Vec<(String, String)> {
@@ -9,8 +13,7 @@ pub fn generator(input: &str) -> Vec<(String, String)> {
     }).collect()
 }
 
-#[aoc(day6, part1, recursive)]
-pub fn part1_rec(orbits: &Vec<(String, String)>) -> usize {
+fn adjacency_map(orbits: &[(String, String)]) -> FxHashMap<&str, Vec<&str>> {
     // Create adjacency map
     let mut map: FxHashMap<&str, Vec<&str>> = FxHashMap::default();
     for orbit in orbits {
@@ -33,8 +36,12 @@ pub fn part1_rec(orbits: &Vec<(String, String)>) -> usize {
             bodies.insert(Vec::new());
         }
     }
+    map
+}
 
-    count_orbits_rec(&map, "COM", 0)
+#[aoc(day6, part1, recursive)]
+pub fn part1_rec(orbits: &Vec<(String, String)>) -> usize {
+    count_orbits_rec(&adjacency_map(orbits), "COM", 0)
 }
 
 fn count_orbits_rec(map: &FxHashMap<&str, Vec<&str>>, body: &str, depth: usize) -> usize {
@@ -42,59 +49,284 @@ fn count_orbits_rec(map: &FxHashMap<&str, Vec<&str>>, body: &str, depth: usize)
     depth + orbiting_bodies.iter().map(|b| count_orbits_rec(map, *b, depth + 1usize)).sum::<usize>()
 }
 
-#[derive(Eq,PartialEq,Debug)]
-struct Planet<'a> {
-    name: &'a str,
-    distance: isize,
+/// Same as `part1_rec`, but with an explicit stack so that the depth of the tree is only limited by memory.
+#[aoc(day6, part1, iterative)]
+pub fn part1_iterative(orbits: &Vec<(String, String)>) -> usize {
+    let map = adjacency_map(orbits);
+    let mut total = 0;
+    let mut stack = vec![("COM", 0usize)];
+    while let Some((body, depth)) = stack.pop() {
+        total += depth;
+        let orbiting_bodies = map.get(body).unwrap_or_else(|| panic!("map should contain '{}'", body));
+        stack.extend(orbiting_bodies.iter().map(|b| (*b, depth + 1)));
+    }
+    total
 }
 
-#[aoc(day6,part2)]
-pub fn part2(orbits: &Vec<(String, String)>) -> isize {
-    // Create reverse map
-    let mut map = FxHashMap::default();
-    for orbit in orbits {
-        map.insert(&orbit.1[..], Planet { name: &orbit.0[..], distance: -1 });
+/// Synthetic orbit map with `bodies` bodies (including COM), where body `i` orbits body
+/// `(i - 1) / branching`. `branching == 1` yields a single chain, larger values wide and shallow trees.
+/// The pairs are listed children first, so that solutions can't rely on the input order.
+pub fn synthetic_orbits(bodies: usize, branching: usize) -> Vec<(String, String)> {
+    assert!(branching > 0, "every body except COM needs a center");
+    let name = |i: usize| if i == 0 { "COM".to_owned() } else { format!("B{}", i) };
+    (1..bodies).rev().map(|i| (name((i - 1) / branching), name(i))).collect()
+}
+
+/// Index of a body in an `OrbitMap`.
+pub type BodyId = usize;
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum OrbitError {
+    /// There are no orbits at all.
+    Empty,
+    /// A body directly orbits more than one center.
+    MultipleCenters { body: String, centers: Vec<String> },
+    /// More than one body doesn't orbit anything.
+    MultipleRoots(Vec<String>),
+    /// These bodies orbit each other in a cycle.
+    Cycle(Vec<String>),
+    /// A query referred to a body that isn't part of the map.
+    UnknownBody(String),
+    /// A query needs the center of a body that doesn't orbit anything.
+    NoCenter(String),
+}
+
+impl fmt::Display for OrbitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OrbitError::Empty => write!(f, "the orbit map is empty"),
+            OrbitError::MultipleCenters { body, centers } => write!(f, "{} orbits more than one center: {}", body, centers.join(", ")),
+            OrbitError::MultipleRoots(roots) => write!(f, "more than one body doesn't orbit anything: {}", roots.join(", ")),
+            OrbitError::Cycle(bodies) => write!(f, "orbits form a cycle: {}", bodies.join(")")),
+            OrbitError::UnknownBody(name) => write!(f, "unknown body {}", name),
+            OrbitError::NoCenter(name) => write!(f, "{} doesn't orbit anything", name),
+        }
+    }
+}
+
+impl std::error::Error for OrbitError {}
+
+/// Validated orbit tree with interned body names.
+#[derive(Clone, Debug)]
+pub struct OrbitMap {
+    names: Vec<String>,
+    ids: FxHashMap<String, BodyId>,
+    center: Vec<Option<BodyId>>,
+    satellites: Vec<Vec<BodyId>>,
+    /// Number of direct and indirect orbits of each body, i.e. its distance from the root.
+    depth: Vec<usize>,
+    root: BodyId,
+}
+
+impl OrbitMap {
+    /// Builds the map from `(center, body)` pairs.
+    pub fn new<S: AsRef<str>>(orbits: &[(S, S)]) -> Result<OrbitMap, OrbitError> {
+        let mut names: Vec<String> = Vec::new();
+        let mut ids: FxHashMap<String, BodyId> = FxHashMap::default();
+        let mut intern = |name: &str| -> BodyId {
+            *ids.entry(name.to_owned()).or_insert_with(|| {
+                names.push(name.to_owned());
+                names.len() - 1
+            })
+        };
+        let pairs: Vec<(BodyId, BodyId)> = orbits.iter().map(|(c, b)| (intern(c.as_ref()), intern(b.as_ref()))).collect();
+
+        let mut center: Vec<Option<BodyId>> = vec![None; names.len()];
+        let mut satellites = vec![Vec::new(); names.len()];
+        for &(c, b) in &pairs {
+            match center[b] {
+                Some(existing) if existing != c => {
+                    let mut centers = vec![names[existing].clone(), names[c].clone()];
+                    centers.sort();
+                    return Err(OrbitError::MultipleCenters { body: names[b].clone(), centers });
+                }
+                Some(_) => continue,
+                None => {
+                    center[b] = Some(c);
+                    satellites[c].push(b);
+                }
+            }
+        }
+
+        let roots: Vec<BodyId> = (0..names.len()).filter(|&b| center[b].is_none()).collect();
+        let root = match roots[..] {
+            [root] => root,
+            [] if names.is_empty() => return Err(OrbitError::Empty),
+            [] => return Err(OrbitError::Cycle(Self::find_cycle(&names, &center, 0))),
+            _ => {
+                let mut roots: Vec<String> = roots.iter().map(|&r| names[r].clone()).collect();
+                roots.sort();
+                return Err(OrbitError::MultipleRoots(roots));
+            }
+        };
+
+        // breadth first, so that deep chains don't overflow the stack
+        let mut depth = vec![usize::MAX; names.len()];
+        depth[root] = 0;
+        let mut queue = VecDeque::from(vec![root]);
+        while let Some(body) = queue.pop_front() {
+            for &satellite in &satellites[body] {
+                depth[satellite] = depth[body] + 1;
+                queue.push_back(satellite);
+            }
+        }
+        // with a single root, everything that can't be reached from it hangs off a cycle
+        if let Some(unreached) = depth.iter().position(|&d| d == usize::MAX) {
+            return Err(OrbitError::Cycle(Self::find_cycle(&names, &center, unreached)));
+        }
+
+        Ok(OrbitMap { names, ids, center, satellites, depth, root })
+    }
+
+    /// Follows the centers starting at `start` (which must not lead to a root) until one repeats.
+    fn find_cycle(names: &[String], center: &[Option<BodyId>], start: BodyId) -> Vec<String> {
+        let mut seen = vec![false; names.len()];
+        let mut body = start;
+        while !seen[body] {
+            seen[body] = true;
+            body = center[body].expect("bodies off the root have a center");
+        }
+        let mut cycle = vec![names[body].clone()];
+        let mut next = center[body].expect("on a cycle");
+        while next != body {
+            cycle.push(names[next].clone());
+            next = center[next].expect("on a cycle");
+        }
+        cycle
+    }
+
+    pub fn id(&self, name: &str) -> Result<BodyId, OrbitError> {
+        self.ids.get(name).cloned().ok_or_else(|| OrbitError::UnknownBody(name.to_owned()))
+    }
+
+    pub fn name(&self, id: BodyId) -> &str {
+        &self.names[id]
+    }
+
+    /// Number of distinct bodies in the map.
+    pub fn body_count(&self) -> usize {
+        self.names.len()
+    }
+
+    /// The body that doesn't orbit anything (COM in the puzzle).
+    pub fn root(&self) -> &str {
+        self.name(self.root)
+    }
+
+    /// The body that `name` directly orbits, if any.
+    pub fn center(&self, name: &str) -> Result<Option<&str>, OrbitError> {
+        Ok(self.center[self.id(name)?].map(|c| self.name(c)))
     }
 
-    // Escape path from YOU all the way to COM
-    let max_distance = escape_from_you(&mut map);
+    /// Bodies that directly orbit `name`.
+    pub fn satellites(&self, name: &str) -> Result<Vec<&str>, OrbitError> {
+        Ok(self.satellites[self.id(name)?].iter().map(|&s| self.name(s)).collect())
+    }
+
+    /// Number of direct and indirect orbits of `name`.
+    pub fn depth(&self, name: &str) -> Result<usize, OrbitError> {
+        Ok(self.depth[self.id(name)?])
+    }
+
+    /// Total number of direct and indirect orbits in the map (part 1).
+    pub fn total_orbits(&self) -> usize {
+        self.depth.iter().sum()
+    }
 
-    //eprintln!("MAP: {:#?}", map);
+    fn lca_id(&self, mut a: BodyId, mut b: BodyId) -> BodyId {
+        while self.depth[a] > self.depth[b] {
+            a = self.center[a].expect("only the root has no center");
+        }
+        while self.depth[b] > self.depth[a] {
+            b = self.center[b].expect("only the root has no center");
+        }
+        while a != b {
+            a = self.center[a].expect("only the root has no center");
+            b = self.center[b].expect("only the root has no center");
+        }
+        a
+    }
 
-    // Escape path from SAN up to previous escape path
-    escape_from_santa(&mut map, max_distance)
+    /// The deepest body that both `a` and `b` (directly or indirectly) orbit, or are.
+    pub fn lowest_common_ancestor(&self, a: &str, b: &str) -> Result<&str, OrbitError> {
+        Ok(self.name(self.lca_id(self.id(a)?, self.id(b)?)))
+    }
+
+    /// Number of orbits between the two bodies, going through their lowest common ancestor.
+    pub fn distance(&self, a: &str, b: &str) -> Result<usize, OrbitError> {
+        let (a, b) = (self.id(a)?, self.id(b)?);
+        let lca = self.lca_id(a, b);
+        Ok(self.depth[a] + self.depth[b] - 2 * self.depth[lca])
+    }
+
+    fn center_of(&self, name: &str) -> Result<&str, OrbitError> {
+        self.center(name)?.ok_or_else(|| OrbitError::NoCenter(name.to_owned()))
+    }
+
+    /// Minimal number of orbital transfers to get from the object `from` orbits to the object `to` orbits (part 2).
+    pub fn transfer_distance(&self, from: &str, to: &str) -> Result<usize, OrbitError> {
+        self.distance(self.center_of(from)?, self.center_of(to)?)
+    }
+
+    /// Bodies on the way from `a` to `b` (both included), going through their lowest common ancestor.
+    pub fn path(&self, a: &str, b: &str) -> Result<Vec<&str>, OrbitError> {
+        let (a, b) = (self.id(a)?, self.id(b)?);
+        let lca = self.lca_id(a, b);
+        let up = |mut body: BodyId| {
+            let mut bodies = Vec::new();
+            while body != lca {
+                bodies.push(body);
+                body = self.center[body].expect("only the root has no center");
+            }
+            bodies
+        };
+        let mut path = up(a);
+        path.push(lca);
+        path.extend(up(b).into_iter().rev());
+        Ok(path.into_iter().map(|body| self.name(body)).collect())
+    }
+
+    /// Bodies visited by the orbital transfers from the object `from` orbits to the object `to` orbits.
+    pub fn transfer_path(&self, from: &str, to: &str) -> Result<Vec<&str>, OrbitError> {
+        self.path(self.center_of(from)?, self.center_of(to)?)
+    }
+
+    /// Writes the orbit tree as a Graphviz digraph with an edge from every center to each of its
+    /// satellites. Bodies in `highlight` and the edges between consecutive ones are drawn in red,
+    /// e.g. for the result of `transfer_path`.
+    pub fn write_dot<W: Write>(&self, out: &mut W, highlight: &[&str]) -> io::Result<()> {
+        let mut highlighted = vec![false; self.names.len()];
+        for id in highlight.iter().filter_map(|name| self.ids.get(*name)) {
+            highlighted[*id] = true;
+        }
+        let highlighted_edges: FxHashSet<(BodyId, BodyId)> = highlight.windows(2)
+            .filter_map(|w| Some((*self.ids.get(w[0])?, *self.ids.get(w[1])?)))
+            .flat_map(|(a, b)| vec![(a, b), (b, a)])
+            .collect();
+
+        writeln!(out, "digraph orbits {{")?;
+        writeln!(out, "    rankdir=LR;")?;
+        for name in self.names.iter().zip(&highlighted).filter(|(_, h)| **h).map(|(name, _)| name) {
+            writeln!(out, "    {} [color=red, style=filled, fillcolor=mistyrose];", quote(name))?;
+        }
+        for (center, satellites) in self.satellites.iter().enumerate() {
+            for &satellite in satellites {
+                let style = if highlighted_edges.contains(&(center, satellite)) { " [color=red, penwidth=2]" } else { "" };
+                writeln!(out, "    {} -> {}{};", quote(self.name(center)), quote(self.name(satellite)), style)?;
+            }
+        }
+        writeln!(out, "}}")
+    }
 }
 
-fn escape_from_santa(map: &mut FxHashMap<&str, Planet>, max_escape_distance: isize) -> isize {
-    let mut current_name = "SAN";
-    let mut current_distance = 0isize;
-    while current_name != "COM" {
-        let next_planet = map.get(current_name).unwrap_or_else(|| panic!("cannot find parent body of {}", current_name));
-        current_name = next_planet.name;
-        if next_planet.distance >= 0 {
-            // found escape path from YOU
-            //eprintln!("Found escape path on planet {}, marked with a red {}. own distance {}", next_planet.name, next_planet.distance, current_distance);
-            return current_distance + next_planet.distance - 2;
-        } else {
-            // continue escape
-            //eprintln!("didn't find escape path yet on planet {} (d:{}), distance travelled is {}", next_planet.name,next_planet.distance, current_distance);
-            current_distance += 1;
-        }
-    }
-    return current_distance + max_escape_distance - 2;
+#[aoc(day6, part1, orbit_map)]
+pub fn part1_orbit_map(orbits: &Vec<(String, String)>) -> usize {
+    OrbitMap::new(orbits).expect("valid orbit map").total_orbits()
 }
 
-fn escape_from_you(map: &mut FxHashMap<&str, Planet>) -> isize {
-    let mut current_name = "YOU";
-    let mut current_distance = 0isize;
-    while current_name != "COM" {
-        let mut next_planet = map.get_mut(current_name).unwrap_or_else(|| panic!("cannot find parent body of {}", current_name));
-        next_planet.distance = current_distance;
-        //eprintln!("Mark planet {} with red {}", next_planet.name, current_distance);
-        current_distance += 1;
-        current_name = next_planet.name;
-    }
-    current_distance
+#[aoc(day6,part2)]
+pub fn part2(orbits: &Vec<(String, String)>) -> usize {
+    OrbitMap::new(orbits).expect("valid orbit map").transfer_distance("YOU", "SAN").expect("YOU and SAN orbit something")
 }
 
 #[cfg(test)]
@@ -216,4 +448,147 @@ COM)Q
 ");
         assert_eq!(part2(&orbits), 1);
     }
+
+    const EXAMPLE_2: &str = "COM)B
+B)C
+C)D
+D)E
+E)F
+B)G
+G)H
+D)I
+E)J
+J)K
+K)L
+K)YOU
+I)SAN
+";
+
+    #[test]
+    fn orbit_map_queries() {
+        let map = OrbitMap::new(&generator(EXAMPLE_2)).unwrap();
+        assert_eq!(map.body_count(), 14);
+        assert_eq!(map.root(), "COM");
+        assert_eq!(map.total_orbits(), 42 + 7 + 5);
+        assert_eq!(map.depth("COM"), Ok(0));
+        assert_eq!(map.depth("L"), Ok(7));
+        assert_eq!(map.center("YOU"), Ok(Some("K")));
+        assert_eq!(map.center("COM"), Ok(None));
+        assert_eq!(map.satellites("E"), Ok(vec!["F", "J"]));
+        assert_eq!(map.lowest_common_ancestor("YOU", "SAN"), Ok("D"));
+        assert_eq!(map.lowest_common_ancestor("L", "J"), Ok("J"));
+        assert_eq!(map.lowest_common_ancestor("H", "SAN"), Ok("B"));
+        assert_eq!(map.distance("YOU", "SAN"), Ok(6));
+        assert_eq!(map.distance("F", "F"), Ok(0));
+        assert_eq!(map.transfer_distance("YOU", "SAN"), Ok(4));
+        assert_eq!(map.transfer_distance("H", "L"), Ok(6));
+        assert_eq!(map.transfer_distance("COM", "SAN"), Err(OrbitError::NoCenter("COM".to_owned())));
+        assert_eq!(map.depth("X"), Err(OrbitError::UnknownBody("X".to_owned())));
+        assert_eq!(map.id(map.name(3)), Ok(3));
+    }
+
+    #[test]
+    fn orbit_map_part1() {
+        let orbits = generator("COM)B
+B)C
+C)D
+D)E
+E)F
+B)G
+G)H
+D)I
+E)J
+J)K
+K)L
+");
+        assert_eq!(part1_orbit_map(&orbits), 42);
+    }
+
+    #[test]
+    fn orbit_map_validation() {
+        let empty: Vec<(String, String)> = Vec::new();
+        assert_eq!(OrbitMap::new(&empty).unwrap_err(), OrbitError::Empty);
+        assert_eq!(OrbitMap::new(&generator("COM)A\nX)B\n")).unwrap_err(), OrbitError::MultipleRoots(vec!["COM".to_owned(), "X".to_owned()]));
+        assert_eq!(OrbitMap::new(&generator("COM)A\nB)A\n")).unwrap_err(),
+                   OrbitError::MultipleCenters { body: "A".to_owned(), centers: vec!["B".to_owned(), "COM".to_owned()] });
+        match OrbitMap::new(&generator("A)B\nB)C\nC)A\n")).unwrap_err() {
+            OrbitError::Cycle(mut bodies) => {
+                bodies.sort();
+                assert_eq!(bodies, vec!["A", "B", "C"]);
+            }
+            other => panic!("expected a cycle, got {:?}", other),
+        }
+        // a cycle next to a proper tree
+        match OrbitMap::new(&generator("COM)A\nB)C\nC)D\nD)B\nD)E\n")).unwrap_err() {
+            OrbitError::Cycle(bodies) => assert_eq!(bodies.len(), 3, "{:?}", bodies),
+            other => panic!("expected a cycle, got {:?}", other),
+        }
+        // duplicate lines are fine
+        assert_eq!(OrbitMap::new(&generator("COM)A\nCOM)A\n")).unwrap().total_orbits(), 1);
+    }
+
+    #[test]
+    fn part1_iterative_example_1() {
+        let orbits = generator("COM)B
+B)C
+C)D
+D)E
+E)F
+B)G
+G)H
+D)I
+E)J
+J)K
+K)L
+");
+        assert_eq!(part1_iterative(&orbits), 42);
+    }
+
+    #[test]
+    fn synthetic_shapes() {
+        // complete binary tree with 7 bodies: 2 at depth 1, 4 at depth 2
+        let tree = synthetic_orbits(7, 2);
+        assert_eq!(tree.len(), 6);
+        assert_eq!(part1_iterative(&tree), 2 + 4 * 2);
+        for (bodies, branching) in [(2, 1), (100, 1), (100, 3), (1000, 10), (500, 1000)] {
+            let orbits = synthetic_orbits(bodies, branching);
+            let expected = part1_rec(&orbits);
+            assert_eq!(part1_iterative(&orbits), expected, "{} bodies, branching {}", bodies, branching);
+            assert_eq!(part1_orbit_map(&orbits), expected, "{} bodies, branching {}", bodies, branching);
+        }
+    }
+
+    #[test]
+    fn part1_iterative_deep_chain() {
+        // deep enough to overflow the default test thread stack with part1_rec
+        let n = 300_000;
+        let chain = synthetic_orbits(n, 1);
+        assert_eq!(part1_iterative(&chain), n * (n - 1) / 2);
+        assert_eq!(part1_orbit_map(&chain), n * (n - 1) / 2);
+    }
+
+    #[test]
+    fn orbit_map_paths() {
+        let map = OrbitMap::new(&generator(EXAMPLE_2)).unwrap();
+        assert_eq!(map.path("YOU", "SAN"), Ok(vec!["YOU", "K", "J", "E", "D", "I", "SAN"]));
+        assert_eq!(map.path("E", "L"), Ok(vec!["E", "J", "K", "L"]));
+        assert_eq!(map.path("C", "C"), Ok(vec!["C"]));
+        assert_eq!(map.transfer_path("YOU", "SAN"), Ok(vec!["K", "J", "E", "D", "I"]));
+    }
+
+    #[test]
+    fn orbit_map_dot() {
+        let map = OrbitMap::new(&generator("COM)B\nB)YOU\nCOM)SAN\n")).unwrap();
+        let mut out = Vec::new();
+        map.write_dot(&mut out, &map.transfer_path("YOU", "SAN").unwrap()).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), r#"digraph orbits {
+    rankdir=LR;
+    "COM" [color=red, style=filled, fillcolor=mistyrose];
+    "B" [color=red, style=filled, fillcolor=mistyrose];
+    "COM" -> "B" [color=red, penwidth=2];
+    "COM" -> "SAN";
+    "B" -> "YOU";
+}
+"#);
+    }
 }
\ No newline at end of file
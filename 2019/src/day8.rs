@@ -1,67 +1,41 @@
+use crate::space_image::{SpaceImage, sif};
 
 #[aoc_generator(day8)]
 pub fn generator(input: &str) -> Vec<u8> {
     input.chars().filter(|c| *c != '\n').map(|c| c.to_digit(10).expect("digits only") as u8).collect()
 }
 
-#[aoc(day8, part1)]
-pub fn part1(input: &Vec<u8>) -> i16 {
-    check_layer(input, 25 * 6)
-}
-
-fn check_layer(input: &Vec<u8>, stride: usize) -> i16 {
-    let mut idx = 0;
-    let mut best_layer_code = -1;
-    let mut best_layer_score = stride as i16 + 1;
-    while idx < input.len() {
-        // next layer
-        let mut freq = [0i16; 10];
-        let end = idx + stride;
-        while idx < end {
-            // next pixel
-            freq[input[idx] as usize] += 1;
-            idx += 1;
-        }
+const WIDTH: usize = 25;
+const HEIGHT: usize = 6;
 
-        // evaluate layer
-        if freq[0] < best_layer_score {
-            best_layer_code = freq[1] * freq[2];
-            best_layer_score = freq[0];
-        }
-    }
-    best_layer_code
+fn image(input: &[u8]) -> SpaceImage {
+    SpaceImage::new(Vec::from(input), WIDTH, HEIGHT).expect("valid space image")
 }
 
-fn z_merge(input: &[u8], stride: usize) -> Vec<u8> {
-    // iterate in reverse to process layers from back to front
-    let mut screen = vec![0; stride];
-    let mut idx = input.len() - 1;
-    loop {
-        let color = input[idx];
-        if color < 2 {
-            screen[idx % stride] = color;
-        }
-
-        if idx == 0 {
-            break;
-        }
-        idx -= 1;
-    }
+#[aoc(day8, part1)]
+pub fn part1(input: &Vec<u8>) -> usize {
+    check_layer(&image(input))
+}
 
-    screen
+/// Number of 1 digits multiplied by the number of 2 digits on the layer with the fewest 0 digits
+fn check_layer(image: &SpaceImage) -> usize {
+    let freq = image.layers()
+        .map(|layer| layer.histogram())
+        .min_by_key(|freq| freq[0])
+        .expect("at least one layer");
+    freq[1] * freq[2]
 }
 
-fn render(input: &[u8], width: usize) -> String {
-    let mut buf = String::with_capacity(input.len()/width + input.len() + 5);
+fn render(image: &SpaceImage) -> String {
+    let mut buf = String::with_capacity(image.layer_size() * 3 + image.height() + 1);
 
     buf.push('\n');
-    for line in input.chunks_exact(width) {
+    for line in image.layers().next().expect("at least one layer").rows() {
         for c in line {
-            //buf.push(('0' as u8 + *c) as char);
-            if *c == 0 {
-                buf.push(' ');
+            if *c == sif::WHITE {
+                buf.push('\u{2588}');
             } else {
-                buf.push_str("\u{2588}");
+                buf.push(' ');
             }
         }
         buf.push('\n')
@@ -72,7 +46,14 @@ fn render(input: &[u8], width: usize) -> String {
 
 #[aoc(day8,part2)]
 pub fn part2(input: &Vec<u8>) -> String {
-    render(&z_merge(&input[..], 25 * 6), 25)
+    let merged = image(input).composite(sif::TRANSPARENT);
+    let pixels: Vec<bool> = merged.layers().next().expect("one layer").pixels().iter().map(|c| *c == sif::WHITE).collect();
+    crate::ocr::recognize(&pixels, merged.width()).expect("image to show letters")
+}
+
+#[aoc(day8,part2,render)]
+pub fn part2_render(input: &Vec<u8>) -> String {
+    render(&image(input).composite(sif::TRANSPARENT))
 }
 
 #[cfg(test)]
@@ -86,11 +67,29 @@ mod tests {
 
     #[test]
     fn part1_example1() {
-        assert_eq!(check_layer(&generator("123\n126\n\n789\n012\n"), 6), 4);
+        let image = SpaceImage::new(generator("123\n126\n\n789\n012\n"), 3, 2).expect("valid");
+        assert_eq!(check_layer(&image), 4);
     }
 
     #[test]
     fn part2_example1_flat() {
-        assert_eq!(z_merge(&generator("0222\n1122\n2212\n0000"), 4), vec![0,1,1,0]);
+        let image = SpaceImage::new(generator("0222\n1122\n2212\n0000"), 4, 1).expect("valid");
+        assert_eq!(image.composite(sif::TRANSPARENT).to_string(), "0110");
+    }
+
+    #[test]
+    fn part2_example1_render() {
+        let image = SpaceImage::new(generator("0222\n1122\n2212\n0000"), 2, 2).expect("valid");
+        assert_eq!(render(&image.composite(sif::TRANSPARENT)), "\n \u{2588}\n\u{2588} \n");
+    }
+
+    #[test]
+    fn part2_letters() {
+        // an "L", the front layer is transparent except for the bottom line
+        let front = "222222222222222222221111";
+        let back = "100010001000100010000000";
+        let image = SpaceImage::new(generator(&format!("{}{}", front, back)), 4, 6).expect("valid");
+        let merged: Vec<bool> = image.composite(sif::TRANSPARENT).to_string().chars().map(|c| c == '1').collect();
+        assert_eq!(crate::ocr::recognize(&merged, 4), Some("L".to_owned()));
     }
 }
\ No newline at end of file
@@ -0,0 +1,6 @@
+//! Helpers for writing Graphviz DOT files (day 6 orbits, day 14 reactions).
+
+/// Quotes `id` so that it can be used as a node name or label in a DOT file.
+pub(crate) fn quote(id: &str) -> String {
+    format!("\"{}\"", id.replace('\\', "\\\\").replace('"', "\\\""))
+}
@@ -30,5 +30,9 @@ pub mod day17;
 pub mod day18;
 
 mod intcode;
+mod dot;
+mod ocr;
+pub mod number_theory;
+pub mod space_image;
 
 aoc_lib! { year = 2019 }
\ No newline at end of file
@@ -0,0 +1,122 @@
+//! Small number theory helpers. All operations work on `u128` (except for `gcd_u64` for hot loops)
+//! and report overflow as `None` instead of wrapping.
+
+/// Greatest common divisor (Euclid). `gcd(0, 0)` is 0.
+pub fn gcd(mut a: u128, mut b: u128) -> u128 {
+    while b != 0 {
+        let r = a % b;
+        a = b;
+        b = r;
+    }
+    a
+}
+
+/// Same as `gcd`, but on `u64` using the binary GCD algorithm, which needs no division.
+pub fn gcd_u64(mut a: u64, mut b: u64) -> u64 {
+    if a == 0 || b == 0 {
+        return a | b;
+    }
+    let shift = (a | b).trailing_zeros();
+    a >>= a.trailing_zeros();
+    loop {
+        b >>= b.trailing_zeros();
+        if a > b {
+            std::mem::swap(&mut a, &mut b);
+        }
+        b -= a;
+        if b == 0 {
+            return a << shift;
+        }
+    }
+}
+
+/// Least common multiple, `None` on overflow. `lcm(x, 0)` is 0.
+pub fn lcm(a: u128, b: u128) -> Option<u128> {
+    if a == 0 || b == 0 {
+        return Some(0);
+    }
+    (a / gcd(a, b)).checked_mul(b)
+}
+
+/// Least common multiple of all numbers, `None` on overflow. The LCM of no numbers is 1.
+pub fn lcm_all(numbers: impl IntoIterator<Item=u128>) -> Option<u128> {
+    numbers.into_iter().try_fold(1u128, lcm)
+}
+
+/// Prime factorization by trial division as (prime, exponent) pairs in ascending order.
+/// 0 and 1 have no prime factors.
+pub fn prime_factors(mut n: u128) -> Vec<(u128, u32)> {
+    let mut factors = Vec::new();
+    if n < 2 {
+        return factors;
+    }
+    let mut p = 2u128;
+    // p * p overflowing means p is larger than any remaining composite factor
+    while p.checked_mul(p).is_some_and(|sq| sq <= n) {
+        let mut exponent = 0;
+        while n.is_multiple_of(p) {
+            n /= p;
+            exponent += 1;
+        }
+        if exponent > 0 {
+            factors.push((p, exponent));
+        }
+        p += if p == 2 { 1 } else { 2 };
+    }
+    if n > 1 {
+        factors.push((n, 1));
+    }
+    factors
+}
+
+/// Multiplies the factorization back together, `None` on overflow.
+pub fn from_prime_factors(factors: &[(u128, u32)]) -> Option<u128> {
+    factors.iter().try_fold(1u128, |acc, (p, e)| p.checked_pow(*e).and_then(|f| acc.checked_mul(f)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gcd_small() {
+        assert_eq!(gcd(12, 18), 6);
+        assert_eq!(gcd(17, 5), 1);
+        assert_eq!(gcd(0, 5), 5);
+        assert_eq!(gcd(0, 0), 0);
+    }
+
+    #[test]
+    fn gcd_u64_matches_gcd() {
+        for a in 0..60u64 {
+            for b in 0..60u64 {
+                assert_eq!(gcd_u64(a, b) as u128, gcd(a as u128, b as u128), "gcd({}, {})", a, b);
+            }
+        }
+        assert_eq!(gcd_u64(1 << 40, 3 << 20), 1 << 20);
+    }
+
+    #[test]
+    fn lcm_small() {
+        assert_eq!(lcm(4, 6), Some(12));
+        assert_eq!(lcm(0, 6), Some(0));
+        assert_eq!(lcm_all(vec![18, 28, 44]), Some(2772));
+        assert_eq!(lcm_all(vec![]), Some(1));
+    }
+
+    #[test]
+    fn lcm_overflow() {
+        assert_eq!(lcm(u128::MAX, u128::MAX - 1), None);
+        assert_eq!(lcm(u128::MAX, u128::MAX), Some(u128::MAX));
+    }
+
+    #[test]
+    fn factorization() {
+        assert_eq!(prime_factors(1), vec![]);
+        assert_eq!(prime_factors(2772), vec![(2, 2), (3, 2), (7, 1), (11, 1)]);
+        assert_eq!(prime_factors(286332), vec![(2, 2), (3, 1), (107, 1), (223, 1)]);
+        assert_eq!(prime_factors(1 << 100), vec![(2, 100)]);
+        assert_eq!(from_prime_factors(&prime_factors(4686774924)), Some(4686774924));
+        assert_eq!(from_prime_factors(&[(2, 200)]), None);
+    }
+}
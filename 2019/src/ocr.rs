@@ -0,0 +1,184 @@
+//! Recognizes the block letters that some puzzles draw as their answer (e.g. day 8 and day 11).
+//! Letters are 6 pixels high and sit in cells 5 pixels wide: usually 4 pixels followed by a blank
+//! column, but `Y` fills its whole cell.
+
+const GLYPH_HEIGHT: usize = 6;
+const CELL_WIDTH: usize = 5;
+
+static GLYPHS: [(char, [&str; GLYPH_HEIGHT]); 18] = [
+    ('A', [".##.", "#..#", "#..#", "####", "#..#", "#..#"]),
+    ('B', ["###.", "#..#", "###.", "#..#", "#..#", "###."]),
+    ('C', [".##.", "#..#", "#...", "#...", "#..#", ".##."]),
+    ('E', ["####", "#...", "###.", "#...", "#...", "####"]),
+    ('F', ["####", "#...", "###.", "#...", "#...", "#..."]),
+    ('G', [".##.", "#..#", "#...", "#.##", "#..#", ".###"]),
+    ('H', ["#..#", "#..#", "####", "#..#", "#..#", "#..#"]),
+    ('I', ["###", ".#.", ".#.", ".#.", ".#.", "###"]),
+    ('J', ["..##", "...#", "...#", "...#", "#..#", ".##."]),
+    ('K', ["#..#", "#.#.", "##..", "#.#.", "#.#.", "#..#"]),
+    ('L', ["#...", "#...", "#...", "#...", "#...", "####"]),
+    ('O', [".##.", "#..#", "#..#", "#..#", "#..#", ".##."]),
+    ('P', ["###.", "#..#", "#..#", "###.", "#...", "#..."]),
+    ('R', ["###.", "#..#", "#..#", "###.", "#.#.", "#..#"]),
+    ('S', [".###", "#...", "#...", ".##.", "...#", "###."]),
+    ('U', ["#..#", "#..#", "#..#", "#..#", "#..#", ".##."]),
+    ('Y', ["#...#", "#...#", ".#.#.", "..#..", "..#..", "..#.."]),
+    ('Z', ["####", "...#", "..#.", ".#..", "#...", "####"]),
+];
+
+/// Reads the letters in a row-major pixel grid of the given width (`true` = lit pixel).
+/// Blank rows and columns around the letters are ignored.
+/// Returns `None` if the lit pixels aren't exactly one line of known letters.
+pub(crate) fn recognize(pixels: &[bool], width: usize) -> Option<String> {
+    if width == 0 {
+        return Some(String::new());
+    }
+    let height = pixels.len() / width;
+    let lit = |x: usize, y: usize| pixels[y * width + x];
+
+    let rows: Vec<usize> = (0..height).filter(|y| (0..width).any(|x| lit(x, *y))).collect();
+    let top = match rows.first() {
+        Some(top) => *top,
+        None => return Some(String::new()),
+    };
+    if rows.last().expect("non-empty") - top + 1 != GLYPH_HEIGHT {
+        return None;
+    }
+
+    let column = |x: usize| -> [bool; GLYPH_HEIGHT] {
+        let mut column = [false; GLYPH_HEIGHT];
+        for (dy, pixel) in column.iter_mut().enumerate() {
+            *pixel = lit(x, top + dy);
+        }
+        column
+    };
+
+    let is_lit = |x: &usize| column(*x).iter().any(|p| *p);
+    let left = (0..width).find(is_lit).expect("a row is lit") as isize;
+    let right = (0..width).rev().find(is_lit).expect("a row is lit") as isize;
+    // cells are 5 columns wide no matter where the ink is, so splitting at blank columns would glue
+    // `Y` to the next letter. The first letter might start with blank columns, so try every offset.
+    (0..CELL_WIDTH as isize).find_map(|offset| {
+        let mut letters = String::new();
+        for start in (left - offset..=right).step_by(CELL_WIDTH) {
+            let mut cell: Vec<[bool; GLYPH_HEIGHT]> = (start..start + CELL_WIDTH as isize)
+                .map(|x| if 0 <= x && x < width as isize { column(x as usize) } else { [false; GLYPH_HEIGHT] })
+                .collect();
+            trim_blank_columns(&mut cell);
+            if !cell.is_empty() {
+                letters.push(match_glyph(&cell)?);
+            }
+        }
+        Some(letters)
+    })
+}
+
+fn match_glyph(segment: &[[bool; GLYPH_HEIGHT]]) -> Option<char> {
+    GLYPHS.iter()
+        .find(|(_, rows)| glyph_columns(rows) == segment)
+        .map(|(letter, _)| *letter)
+}
+
+/// Columns of a glyph with blank columns on either side removed (e.g. `J` starts with a gap).
+fn glyph_columns(rows: &[&str; GLYPH_HEIGHT]) -> Vec<[bool; GLYPH_HEIGHT]> {
+    let width = rows[0].len();
+    let mut columns: Vec<[bool; GLYPH_HEIGHT]> = (0..width).map(|x| {
+        let mut column = [false; GLYPH_HEIGHT];
+        for (pixel, row) in column.iter_mut().zip(rows.iter()) {
+            *pixel = row.as_bytes()[x] == b'#';
+        }
+        column
+    }).collect();
+    trim_blank_columns(&mut columns);
+    columns
+}
+
+fn trim_blank_columns(columns: &mut Vec<[bool; GLYPH_HEIGHT]>) {
+    while columns.first().is_some_and(|c| c.iter().all(|p| !*p)) {
+        columns.remove(0);
+    }
+    while columns.last().is_some_and(|c| c.iter().all(|p| !*p)) {
+        columns.pop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pixels(art: &str) -> (Vec<bool>, usize) {
+        let width = art.lines().map(|l| l.len()).max().unwrap_or(0);
+        let pixels = art.lines()
+            .flat_map(|l| l.bytes().map(|b| b == b'#').chain(std::iter::repeat(false)).take(width))
+            .collect();
+        (pixels, width)
+    }
+
+    #[test]
+    fn every_glyph() {
+        for (letter, rows) in GLYPHS.iter() {
+            let (grid, width) = pixels(&rows.join("\n"));
+            assert_eq!(recognize(&grid, width), Some(letter.to_string()));
+        }
+    }
+
+    #[test]
+    fn word_with_margin() {
+        let (grid, width) = pixels("\
+.........................
+.###..###....##..##..####
+.#..#.#..#....#.#..#.#...
+.#..#.#..#....#.#....###.
+.###..###.....#.#....#...
+.#.#..#....#..#.#..#.#...
+.#..#.#.....##...##..#...
+.........................");
+        assert_eq!(recognize(&grid, width), Some("RPJCF".to_owned()));
+    }
+
+    /// Renders `word` the way the puzzles do, one letter per 5 column cell.
+    fn word_pixels(word: &str) -> (Vec<bool>, usize) {
+        let art: Vec<String> = (0..GLYPH_HEIGHT).map(|y| word.chars().map(|letter| {
+            let (_, rows) = GLYPHS.iter().find(|(l, _)| *l == letter).expect("known letter");
+            format!("{:.<w$}", rows[y], w = CELL_WIDTH)
+        }).collect()).collect();
+        pixels(&art.join("\n"))
+    }
+
+    #[test]
+    fn y_followed_by_letter() {
+        for word in ["YA", "HEY", "YAYZ", "YYY"] {
+            let (grid, width) = word_pixels(word);
+            assert_eq!(recognize(&grid, width), Some(word.to_owned()), "{}", word);
+        }
+    }
+
+    #[test]
+    fn every_glyph_pair() {
+        for (first, _) in GLYPHS.iter() {
+            for (second, _) in GLYPHS.iter() {
+                let word: String = [*first, *second].iter().collect();
+                let (grid, width) = word_pixels(&word);
+                assert_eq!(recognize(&grid, width), Some(word.clone()));
+            }
+        }
+    }
+
+    #[test]
+    fn unknown_glyph() {
+        let (grid, width) = pixels("####\n####\n####\n####\n####\n####");
+        assert_eq!(recognize(&grid, width), None);
+    }
+
+    #[test]
+    fn wrong_height() {
+        let (grid, width) = pixels("#\n#\n#");
+        assert_eq!(recognize(&grid, width), None);
+    }
+
+    #[test]
+    fn blank() {
+        let (grid, width) = pixels("....\n....");
+        assert_eq!(recognize(&grid, width), Some(String::new()));
+    }
+}
@@ -0,0 +1,221 @@
+//! Images in the Space Image Format (SIF): a sequence of equally sized layers of digit "colors",
+//! stored front to back. See day 8.
+
+use std::fmt::{self, Display, Formatter};
+use std::io::{self, Write};
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct SpaceImage {
+    width: usize,
+    height: usize,
+    data: Vec<u8>,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum ImageError {
+    /// Width or height is zero
+    NoPixels,
+    /// There is not a single layer of data
+    Empty,
+    /// The data does not end on a layer boundary
+    Truncated { layer_size: usize, actual: usize },
+    /// A character in the textual format that is not a digit
+    InvalidPixel { position: usize, found: char },
+    /// A pixel in raw image data that is not a color (0-9)
+    InvalidColor { position: usize, value: u8 },
+}
+
+impl Display for ImageError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ImageError::NoPixels => write!(f, "image dimensions must not be zero"),
+            ImageError::Empty => write!(f, "image does not contain any layers"),
+            ImageError::Truncated { layer_size, actual } =>
+                write!(f, "image data ({} pixels) is not a multiple of the layer size ({} pixels)", actual, layer_size),
+            ImageError::InvalidPixel { position, found } =>
+                write!(f, "invalid pixel '{}' at position {}", found, position),
+            ImageError::InvalidColor { position, value } =>
+                write!(f, "invalid color {} at position {}", value, position),
+        }
+    }
+}
+
+impl std::error::Error for ImageError {}
+
+/// A single layer of a `SpaceImage`, in row-major order.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct Layer<'a> {
+    width: usize,
+    pixels: &'a [u8],
+}
+
+impl<'a> Layer<'a> {
+    pub fn pixels(&self) -> &'a [u8] {
+        self.pixels
+    }
+
+    pub fn rows(&self) -> impl Iterator<Item=&'a [u8]> {
+        self.pixels.chunks_exact(self.width)
+    }
+
+    /// Number of pixels of each color (index = color)
+    pub fn histogram(&self) -> [usize; 10] {
+        let mut freq = [0usize; 10];
+        for pixel in self.pixels {
+            freq[*pixel as usize] += 1;
+        }
+        freq
+    }
+
+    pub fn count(&self, color: u8) -> usize {
+        self.pixels.iter().filter(|p| **p == color).count()
+    }
+}
+
+impl SpaceImage {
+    pub fn new(data: Vec<u8>, width: usize, height: usize) -> Result<SpaceImage, ImageError> {
+        let layer_size = width * height;
+        if layer_size == 0 {
+            return Err(ImageError::NoPixels);
+        }
+        if let Some(position) = data.iter().position(|p| *p > 9) {
+            return Err(ImageError::InvalidColor { position, value: data[position] });
+        }
+        if data.is_empty() {
+            return Err(ImageError::Empty);
+        }
+        if !data.len().is_multiple_of(layer_size) {
+            return Err(ImageError::Truncated { layer_size, actual: data.len() });
+        }
+        Ok(SpaceImage { width, height, data })
+    }
+
+    /// Parses the textual format (digits, line breaks are ignored).
+    pub fn parse(input: &str, width: usize, height: usize) -> Result<SpaceImage, ImageError> {
+        let data = input.chars()
+            .filter(|c| *c != '\n' && *c != '\r')
+            .enumerate()
+            .map(|(position, c)| c.to_digit(10)
+                .map(|d| d as u8)
+                .ok_or(ImageError::InvalidPixel { position, found: c }))
+            .collect::<Result<Vec<u8>, ImageError>>()?;
+        SpaceImage::new(data, width, height)
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn layer_size(&self) -> usize {
+        self.width * self.height
+    }
+
+    pub fn num_layers(&self) -> usize {
+        self.data.len() / self.layer_size()
+    }
+
+    /// Layers from front to back
+    pub fn layers(&self) -> impl Iterator<Item=Layer<'_>> {
+        let width = self.width;
+        self.data.chunks_exact(self.layer_size()).map(move |pixels| Layer { width, pixels })
+    }
+
+    /// Stacks all layers: each pixel takes the color of the front-most layer where it is not `transparent`.
+    /// Pixels that are transparent in every layer stay `transparent`.
+    pub fn composite(&self, transparent: u8) -> SpaceImage {
+        let mut merged = vec![transparent; self.layer_size()];
+        for layer in self.layers() {
+            for (m, p) in merged.iter_mut().zip(layer.pixels) {
+                if *m == transparent {
+                    *m = *p;
+                }
+            }
+        }
+        SpaceImage { width: self.width, height: self.height, data: merged }
+    }
+
+    /// Writes the front layer as a binary PGM (P5) image, mapping colors to gray levels with `palette`.
+    pub fn write_pgm(&self, out: &mut impl Write, palette: impl Fn(u8) -> u8) -> io::Result<()> {
+        write!(out, "P5\n{} {}\n255\n", self.width, self.height)?;
+        let layer = self.layers().next().expect("validated to have at least one layer");
+        let gray: Vec<u8> = layer.pixels.iter().map(|p| palette(*p)).collect();
+        out.write_all(&gray)
+    }
+}
+
+/// The colors used by the elves: 0 is black, 1 is white and 2 is transparent.
+pub mod sif {
+    pub const BLACK: u8 = 0;
+    pub const WHITE: u8 = 1;
+    pub const TRANSPARENT: u8 = 2;
+
+    /// Gray level for PGM output; transparent is shown as mid-gray.
+    pub fn gray(color: u8) -> u8 {
+        match color {
+            BLACK => 0x00,
+            WHITE => 0xff,
+            _ => 0x80,
+        }
+    }
+}
+
+/// Encodes the image in the textual format (a single line of digits).
+impl Display for SpaceImage {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for pixel in &self.data {
+            write!(f, "{}", pixel)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn layers() {
+        let image = SpaceImage::parse("123456789012", 3, 2).expect("valid");
+        assert_eq!(image.num_layers(), 2);
+        let layers: Vec<Layer> = image.layers().collect();
+        assert_eq!(layers[1].pixels(), &[7, 8, 9, 0, 1, 2]);
+        assert_eq!(layers[1].rows().collect::<Vec<_>>(), vec![&[7, 8, 9][..], &[0, 1, 2][..]]);
+        assert_eq!(layers[0].histogram(), [0, 1, 1, 1, 1, 1, 1, 0, 0, 0]);
+    }
+
+    #[test]
+    fn truncated() {
+        assert_eq!(SpaceImage::parse("1234567", 3, 2), Err(ImageError::Truncated { layer_size: 6, actual: 7 }));
+        assert_eq!(SpaceImage::parse("", 3, 2), Err(ImageError::Empty));
+        assert_eq!(SpaceImage::parse("1", 0, 2), Err(ImageError::NoPixels));
+        assert_eq!(SpaceImage::parse("12\n3x", 2, 2), Err(ImageError::InvalidPixel { position: 3, found: 'x' }));
+        let error = SpaceImage::new(vec![1, 10], 1, 2).unwrap_err();
+        assert_eq!(error, ImageError::InvalidColor { position: 1, value: 10 });
+        assert_eq!(error.to_string(), "invalid color 10 at position 1");
+    }
+
+    #[test]
+    fn composite_other_transparency() {
+        let image = SpaceImage::parse("0555\n1155\n5515\n0000", 2, 2).expect("valid");
+        assert_eq!(image.composite(5).to_string(), "0110");
+        assert_eq!(image.composite(0).to_string(), "1555");
+    }
+
+    #[test]
+    fn roundtrip_text() {
+        let text = "0222112222120000";
+        assert_eq!(SpaceImage::parse(text, 2, 2).expect("valid").to_string(), text);
+    }
+
+    #[test]
+    fn pgm() {
+        let image = SpaceImage::parse("0222112222120000", 2, 2).expect("valid").composite(sif::TRANSPARENT);
+        let mut buf = Vec::new();
+        image.write_pgm(&mut buf, sif::gray).expect("write to vec");
+        assert_eq!(buf, b"P5\n2 2\n255\n\x00\xff\xff\x00".to_vec());
+    }
+}